@@ -0,0 +1,164 @@
+use chip8_core::{CompatibilityProfile, Machine, Quirks};
+use std::{env, fmt::Write as _, fs, process::exit};
+
+const USAGE: &str = "Usage: chip8_cli [options] path/to/game
+
+Runs a ROM without a window and prints the final display.
+
+Options:
+    --frames N       frames to run (default 60)
+    --speed N        instructions per frame (default 10)
+    --seed N         RNG seed (default 0)
+    --profile NAME   vip, schip or xochip (default: the core's defaults)
+    --format NAME    text or pbm (default text)
+    --output PATH    write the display to PATH instead of stdout
+    --hotspots       print where the ROM spent its time to stderr
+    --hash           print the final state hash to stderr";
+
+struct Options {
+    rom: String,
+    frames: usize,
+    speed: usize,
+    seed: u64,
+    quirks: Quirks,
+    pbm: bool,
+    output: Option<String>,
+    hotspots: bool,
+    hash: bool,
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    exit(2);
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: Option<String>) -> T {
+    value
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(|| fail(&format!("{} expects a number", flag)))
+}
+
+fn parse_args() -> Options {
+    let mut options = Options {
+        rom: String::new(),
+        frames: 60,
+        speed: 10,
+        seed: 0,
+        quirks: Quirks::default(),
+        pbm: false,
+        output: None,
+        hotspots: false,
+        hash: false,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => options.frames = number(&arg, args.next()),
+            "--speed" => options.speed = number(&arg, args.next()),
+            "--seed" => options.seed = number(&arg, args.next()),
+            "--profile" => {
+                options.quirks = match args.next().as_deref() {
+                    Some("vip") => CompatibilityProfile::CosmacVip.into(),
+                    Some("schip") => CompatibilityProfile::SuperChip.into(),
+                    Some("xochip") => CompatibilityProfile::XoChip.into(),
+                    _ => fail("--profile expects vip, schip or xochip"),
+                }
+            }
+            "--format" => {
+                options.pbm = match args.next().as_deref() {
+                    Some("text") => false,
+                    Some("pbm") => true,
+                    _ => fail("--format expects text or pbm"),
+                }
+            }
+            "--output" => {
+                options.output = Some(
+                    args.next()
+                        .unwrap_or_else(|| fail("--output expects a path")),
+                )
+            }
+            "--hotspots" => options.hotspots = true,
+            "--hash" => options.hash = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            _ if arg.starts_with("--") => fail(&format!("unknown option {}", arg)),
+            _ if options.rom.is_empty() => options.rom = arg,
+            _ => fail("only one ROM can be given"),
+        }
+    }
+
+    if options.rom.is_empty() {
+        fail("no ROM given");
+    }
+
+    options
+}
+
+fn render(chip8: &Machine, pbm: bool) -> String {
+    let (width, height) = chip8.display_dimensions();
+    let mut out = String::new();
+    if pbm {
+        writeln!(out, "P1\n{} {}", width, height).unwrap();
+    }
+    for row in chip8.get_display().chunks(width) {
+        let line: String = row
+            .iter()
+            .map(|pixel| match (pbm, *pixel) {
+                (true, true) => '1',
+                (true, false) => '0',
+                (false, true) => '#',
+                (false, false) => '.',
+            })
+            .collect();
+        writeln!(out, "{}", line).unwrap();
+    }
+
+    out
+}
+
+fn main() {
+    let options = parse_args();
+
+    let mut chip8 = Machine::new_with_quirks(options.quirks);
+    chip8.set_rng_seed(options.seed);
+    chip8.set_speed(options.speed);
+    if let Err(err) = chip8.load_from_path(&options.rom) {
+        eprintln!("Unable to load ROM: {}", err);
+        exit(1);
+    }
+    if options.hotspots {
+        chip8.enable_profiling();
+    }
+
+    for _ in 0..options.frames {
+        match chip8.run_frame() {
+            Ok(outcome) if outcome.halted => break,
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("Error at {:#05X}: {}", chip8.program_counter(), err);
+                exit(1);
+            }
+        }
+    }
+
+    if options.hotspots {
+        eprint!("{}", chip8.profile_report());
+    }
+    if options.hash {
+        eprintln!("State hash: {:#018x}", chip8.state_hash());
+    }
+
+    let display = render(&chip8, options.pbm);
+    match options.output {
+        Some(path) => {
+            if let Err(err) = fs::write(&path, display) {
+                eprintln!("Unable to write {}: {}", path, err);
+                exit(1);
+            }
+        }
+        None => print!("{}", display),
+    }
+}
@@ -0,0 +1,340 @@
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use core::fmt;
+
+use crate::{opcode::Opcode, START_ADDR};
+
+/// Why a line of source could not be assembled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    /// 1-based source line.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AsmError {}
+
+struct Statement<'a> {
+    line: usize,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+impl Statement<'_> {
+    fn size(&self) -> usize {
+        match self.mnemonic.as_str() {
+            "DB" => self.operands.len(),
+            "DW" => self.operands.len() * 2,
+            "LD" if self.is_long_load() => 4,
+            _ => 2,
+        }
+    }
+
+    fn is_long_load(&self) -> bool {
+        self.operands.len() == 2
+            && self.operands[0].eq_ignore_ascii_case("I")
+            && long_address(self.operands[1]).is_some()
+    }
+}
+
+// What follows the `LONG` keyword of a long load's operand
+fn long_address(operand: &str) -> Option<&str> {
+    let keyword = operand.get(..4)?;
+    let addr = operand.get(4..)?;
+    (keyword.eq_ignore_ascii_case("LONG") && !addr.is_empty()).then(|| addr.trim())
+}
+
+/// Assembles Cowgod-style mnemonics, the same syntax `disassemble` prints,
+/// into a ROM loaded at 0x200.
+///
+/// Each line holds an optional `label:`, then an instruction or a `db`/`dw`
+/// directive with comma-separated operands, then an optional `; comment`.
+/// Numbers are decimal, or hex with a `#` or `0x` prefix, or binary with
+/// `0b`. XO-CHIP's long load is written `LD I, LONG addr`.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut statements = Vec::new();
+    let mut labels = BTreeMap::new();
+    let mut addr = START_ADDR as usize;
+
+    for (i, line) in source.lines().enumerate() {
+        let line_no = i + 1;
+        let mut rest = line.split(';').next().unwrap_or_default().trim();
+        while let Some((label, after)) = rest.split_once(':') {
+            let label = label.trim();
+            if label.is_empty() || label.contains(char::is_whitespace) {
+                break;
+            }
+            if labels.insert(label, addr as u16).is_some() {
+                return Err(error(line_no, format!("duplicate label `{}`", label)));
+            }
+            rest = after.trim();
+        }
+        if rest.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let operands = if operands.trim().is_empty() {
+            Vec::new()
+        } else {
+            operands.split(',').map(str::trim).collect()
+        };
+        let statement = Statement {
+            line: line_no,
+            mnemonic: mnemonic.to_ascii_uppercase(),
+            operands,
+        };
+        addr += statement.size();
+        statements.push(statement);
+    }
+
+    let mut rom = Vec::new();
+    for statement in &statements {
+        let asm = Assembler {
+            line: statement.line,
+            labels: &labels,
+        };
+        asm.emit(statement, &mut rom)?;
+    }
+
+    Ok(rom)
+}
+
+fn error(line: usize, message: String) -> AsmError {
+    AsmError { line, message }
+}
+
+struct Assembler<'a> {
+    line: usize,
+    labels: &'a BTreeMap<&'a str, u16>,
+}
+
+impl Assembler<'_> {
+    fn error(&self, message: String) -> AsmError {
+        error(self.line, message)
+    }
+
+    fn emit(&self, statement: &Statement, rom: &mut Vec<u8>) -> Result<(), AsmError> {
+        match statement.mnemonic.as_str() {
+            "DB" => {
+                for operand in &statement.operands {
+                    rom.push(self.byte(operand)?);
+                }
+            }
+            "DW" => {
+                for operand in &statement.operands {
+                    rom.extend_from_slice(&self.value(operand)?.to_be_bytes());
+                }
+            }
+            "LD" if statement.is_long_load() => {
+                let addr = self.value(long_address(statement.operands[1]).unwrap_or_default())?;
+                rom.extend_from_slice(&Opcode::LoadLongIndex.encode().to_be_bytes());
+                rom.extend_from_slice(&addr.to_be_bytes());
+            }
+            _ => {
+                let op = self.instruction(&statement.mnemonic, &statement.operands)?;
+                rom.extend_from_slice(&op.encode().to_be_bytes());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn instruction(&self, mnemonic: &str, ops: &[&str]) -> Result<Opcode, AsmError> {
+        let op = match (mnemonic, ops) {
+            ("CLS", []) => Opcode::ClearScreen,
+            ("RET", []) => Opcode::Return,
+            ("SCD", [n]) => Opcode::ScrollDown(self.nibble(n)?),
+            ("SCR", []) => Opcode::ScrollRight,
+            ("SCL", []) => Opcode::ScrollLeft,
+            ("EXIT", []) => Opcode::Exit,
+            ("LOW", []) => Opcode::LowRes,
+            ("HIGH", []) => Opcode::HighRes,
+            ("AUDIO", []) => Opcode::LoadAudioPattern,
+            ("PLANE", [n]) => Opcode::SelectPlanes(self.nibble(n)?),
+            ("JP", [addr]) => Opcode::Jump(self.addr(addr)?),
+            ("JP", [v0, addr]) if reg(v0) == Some(0) => {
+                let addr = self.addr(addr)?;
+                Opcode::JumpOffset {
+                    x: (addr >> 8) as usize,
+                    addr,
+                }
+            }
+            ("CALL", [addr]) => Opcode::Call(self.addr(addr)?),
+            ("SE", [x, y]) => match (self.reg(x)?, reg(y)) {
+                (x, Some(y)) => Opcode::SkipEqReg { x, y },
+                (x, None) => Opcode::SkipEqImm {
+                    x,
+                    nn: self.byte(y)?,
+                },
+            },
+            ("SNE", [x, y]) => match (self.reg(x)?, reg(y)) {
+                (x, Some(y)) => Opcode::SkipNeReg { x, y },
+                (x, None) => Opcode::SkipNeImm {
+                    x,
+                    nn: self.byte(y)?,
+                },
+            },
+            ("LD", [dst, src]) => self.load(dst, src)?,
+            ("ADD", [i, x]) if i.eq_ignore_ascii_case("I") => Opcode::AddIndex { x: self.reg(x)? },
+            ("ADD", [x, y]) => match (self.reg(x)?, reg(y)) {
+                (x, Some(y)) => Opcode::AddReg { x, y },
+                (x, None) => Opcode::AddImm {
+                    x,
+                    nn: self.byte(y)?,
+                },
+            },
+            ("OR", [x, y]) => Opcode::Or {
+                x: self.reg(x)?,
+                y: self.reg(y)?,
+            },
+            ("AND", [x, y]) => Opcode::And {
+                x: self.reg(x)?,
+                y: self.reg(y)?,
+            },
+            ("XOR", [x, y]) => Opcode::Xor {
+                x: self.reg(x)?,
+                y: self.reg(y)?,
+            },
+            ("SUB", [x, y]) => Opcode::SubReg {
+                x: self.reg(x)?,
+                y: self.reg(y)?,
+            },
+            ("SUBN", [x, y]) => Opcode::SubNReg {
+                x: self.reg(x)?,
+                y: self.reg(y)?,
+            },
+            // The source register is optional and defaults to the destination
+            ("SHR", [x]) => Opcode::ShiftRight {
+                x: self.reg(x)?,
+                y: self.reg(x)?,
+            },
+            ("SHR", [x, y]) => Opcode::ShiftRight {
+                x: self.reg(x)?,
+                y: self.reg(y)?,
+            },
+            ("SHL", [x]) => Opcode::ShiftLeft {
+                x: self.reg(x)?,
+                y: self.reg(x)?,
+            },
+            ("SHL", [x, y]) => Opcode::ShiftLeft {
+                x: self.reg(x)?,
+                y: self.reg(y)?,
+            },
+            ("RND", [x, nn]) => Opcode::Random {
+                x: self.reg(x)?,
+                nn: self.byte(nn)?,
+            },
+            ("DRW", [x, y, n]) => Opcode::Draw {
+                x: self.reg(x)?,
+                y: self.reg(y)?,
+                n: self.nibble(n)?,
+            },
+            ("SKP", [x]) => Opcode::SkipKeyPressed { x: self.reg(x)? },
+            ("SKNP", [x]) => Opcode::SkipKeyNotPressed { x: self.reg(x)? },
+            _ => {
+                return Err(self.error(format!(
+                    "unknown instruction `{} {}`",
+                    mnemonic,
+                    ops.join(", ")
+                )))
+            }
+        };
+
+        Ok(op)
+    }
+
+    fn load(&self, dst: &str, src: &str) -> Result<Opcode, AsmError> {
+        let upper = |s: &str| s.to_ascii_uppercase();
+        let op = match (upper(dst).as_str(), upper(src).as_str()) {
+            ("I", _) => Opcode::LoadIndex(self.addr(src)?),
+            ("DT", _) => Opcode::SetDelay { x: self.reg(src)? },
+            ("ST", _) => Opcode::SetSound { x: self.reg(src)? },
+            ("F", _) => Opcode::LoadFont { x: self.reg(src)? },
+            ("HF", _) => Opcode::LoadBigFont { x: self.reg(src)? },
+            ("B", _) => Opcode::StoreBcd { x: self.reg(src)? },
+            ("[I]", _) => Opcode::StoreRegs { x: self.reg(src)? },
+            ("R", _) => Opcode::StoreFlags { x: self.reg(src)? },
+            (_, "DT") => Opcode::LoadDelay { x: self.reg(dst)? },
+            (_, "K") => Opcode::WaitKey { x: self.reg(dst)? },
+            (_, "[I]") => Opcode::LoadRegs { x: self.reg(dst)? },
+            (_, "R") => Opcode::LoadFlags { x: self.reg(dst)? },
+            _ => match (self.reg(dst)?, reg(src)) {
+                (x, Some(y)) => Opcode::LoadReg { x, y },
+                (x, None) => Opcode::LoadImm {
+                    x,
+                    nn: self.byte(src)?,
+                },
+            },
+        };
+
+        Ok(op)
+    }
+
+    fn reg(&self, operand: &str) -> Result<usize, AsmError> {
+        reg(operand).ok_or_else(|| self.error(format!("expected a register, found `{}`", operand)))
+    }
+
+    fn value(&self, operand: &str) -> Result<u16, AsmError> {
+        if let Some(value) = number(operand) {
+            return u16::try_from(value)
+                .map_err(|_| self.error(format!("`{}` does not fit in 16 bits", operand)));
+        }
+
+        self.labels
+            .get(operand)
+            .copied()
+            .ok_or_else(|| self.error(format!("unknown label `{}`", operand)))
+    }
+
+    fn limited(&self, operand: &str, max: u16, what: &str) -> Result<u16, AsmError> {
+        let value = self.value(operand)?;
+        if value > max {
+            return Err(self.error(format!("`{}` does not fit in {}", operand, what)));
+        }
+
+        Ok(value)
+    }
+
+    fn addr(&self, operand: &str) -> Result<u16, AsmError> {
+        self.limited(operand, 0xFFF, "12 bits")
+    }
+
+    fn byte(&self, operand: &str) -> Result<u8, AsmError> {
+        self.limited(operand, 0xFF, "a byte")
+            .map(|value| value as u8)
+    }
+
+    fn nibble(&self, operand: &str) -> Result<u8, AsmError> {
+        self.limited(operand, 0xF, "a nibble")
+            .map(|value| value as u8)
+    }
+}
+
+fn reg(operand: &str) -> Option<usize> {
+    let digit = operand.strip_prefix(['V', 'v'])?;
+    if digit.len() != 1 {
+        return None;
+    }
+
+    usize::from_str_radix(digit, 16).ok()
+}
+
+fn number(operand: &str) -> Option<u32> {
+    let lower = operand.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix('#').or_else(|| lower.strip_prefix("0x")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        u32::from_str_radix(bin, 2).ok()
+    } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+        lower.parse().ok()
+    } else {
+        None
+    }
+}
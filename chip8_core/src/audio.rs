@@ -0,0 +1,19 @@
+use alloc::boxed::Box;
+
+use crate::{Machine, AUDIO_PATTERN_SIZE};
+
+/// Receives the buzzer's state changes, for frontends that drive their own
+/// audio device.
+pub trait AudioSink {
+    fn beep_start(&mut self);
+    fn beep_stop(&mut self);
+    /// Called when an XO-CHIP program loads a new sample buffer with `F002`.
+    /// The 128 bits play MSB first at 4000 bits per second.
+    fn pattern_loaded(&mut self, _pattern: &[u8; AUDIO_PATTERN_SIZE]) {}
+}
+
+impl Machine {
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.audio_sink = Some(sink);
+    }
+}
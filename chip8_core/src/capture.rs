@@ -0,0 +1,182 @@
+mod gif;
+mod png;
+
+use alloc::{vec, vec::Vec};
+
+pub use crate::display::{Palette, DEFAULT_PALETTE};
+use crate::Machine;
+
+/// An RGB8 image, 3 bytes per pixel in row-major order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Encodes the image as a PNG file.
+    pub fn to_png(&self) -> Vec<u8> {
+        png::encode_rgb(self)
+    }
+}
+
+impl Machine {
+    /// The display at its native resolution in `DEFAULT_PALETTE`.
+    pub fn screenshot(&self) -> Image {
+        self.screenshot_with(&DEFAULT_PALETTE, 1)
+    }
+
+    /// The display in `palette`, with every pixel scaled to a `scale` by
+    /// `scale` block.
+    pub fn screenshot_with(&self, palette: &Palette, scale: usize) -> Image {
+        let frame = Frame::capture(self);
+        let (width, height) = (frame.width * scale, frame.height * scale);
+        let pixels = frame
+            .scaled(width, height)
+            .into_iter()
+            .flat_map(|color| palette[color as usize])
+            .collect();
+
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Frame {
+    width: usize,
+    height: usize,
+    colors: Vec<u8>,
+    // How many 60 Hz frames the display stayed like this
+    duration: u32,
+}
+
+impl Frame {
+    fn capture(machine: &Machine) -> Self {
+        let (width, height) = machine.display_dimensions();
+        Self {
+            width,
+            height,
+            colors: machine.display_colors(),
+            duration: 1,
+        }
+    }
+
+    /// Color indices resized to `width` by `height` with nearest-neighbour
+    /// scaling.
+    fn scaled(&self, width: usize, height: usize) -> Vec<u8> {
+        let mut out = vec![0; width * height];
+        for (y, row) in out.chunks_exact_mut(width).enumerate() {
+            let src = y * self.height / height * self.width;
+            for (x, color) in row.iter_mut().enumerate() {
+                *color = self.colors[src + x * self.width / width];
+            }
+        }
+
+        out
+    }
+}
+
+/// Accumulates displays, one per 60 Hz frame, and encodes them as an animated
+/// GIF or APNG.
+///
+/// Consecutive identical displays are stored once with a longer duration. If
+/// the program switches resolution, the whole clip is encoded at the highest
+/// one and lores frames are scaled up to fill it.
+#[derive(Debug, Clone)]
+pub struct FrameRecorder {
+    palette: Palette,
+    scale: usize,
+    frames: Vec<Frame>,
+}
+
+impl FrameRecorder {
+    /// A recorder drawing in `palette`, with every pixel scaled to a `scale`
+    /// by `scale` block.
+    pub fn new(palette: Palette, scale: usize) -> Self {
+        Self {
+            palette,
+            scale: scale.max(1),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Adds the current display; call once after every `run_frame`.
+    pub fn capture(&mut self, machine: &Machine) {
+        let frame = Frame::capture(machine);
+        match self.frames.last_mut() {
+            Some(last)
+                if (last.width, last.height, &last.colors)
+                    == (frame.width, frame.height, &frame.colors) =>
+            {
+                last.duration += 1
+            }
+            _ => self.frames.push(frame),
+        }
+    }
+
+    /// Captured 60 Hz frames, counting repeated displays.
+    pub fn frame_count(&self) -> usize {
+        self.frames
+            .iter()
+            .map(|frame| frame.duration as usize)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Encodes the clip as a looping GIF. GIF delays are in hundredths of a
+    /// second and viewers slow down anything shorter than two, so displays
+    /// that last less are merged into the one that follows them.
+    pub fn encode_gif(&self) -> Vec<u8> {
+        let (width, height) = self.canvas();
+        gif::encode(
+            width,
+            height,
+            &self.palette,
+            self.frames
+                .iter()
+                .map(|frame| (frame.scaled(width, height), frame.duration)),
+        )
+    }
+
+    /// Encodes the clip as a looping APNG, which keeps exact 60 Hz timing.
+    pub fn encode_apng(&self) -> Vec<u8> {
+        let (width, height) = self.canvas();
+        png::encode_apng(
+            width,
+            height,
+            &self.palette,
+            self.frames
+                .iter()
+                .map(|frame| (frame.scaled(width, height), frame.duration)),
+        )
+    }
+
+    fn canvas(&self) -> (usize, usize) {
+        let (width, height) = self
+            .frames
+            .iter()
+            .map(|frame| (frame.width, frame.height))
+            .max()
+            .unwrap_or((crate::SCREEN_WIDTH, crate::SCREEN_HEIGHT));
+
+        (width * self.scale, height * self.scale)
+    }
+}
+
+impl Default for FrameRecorder {
+    fn default() -> Self {
+        Self::new(DEFAULT_PALETTE, 1)
+    }
+}
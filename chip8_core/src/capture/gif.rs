@@ -0,0 +1,215 @@
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use super::Palette;
+
+// Two bits per pixel for the four palette entries
+const MIN_CODE_SIZE: u8 = 2;
+const MAX_CODES: u16 = 4096;
+const MIN_DELAY_CS: u32 = 2;
+
+/// Writes `frames` of palette indices, each lasting the given number of 60 Hz
+/// frames, as a GIF89a that loops forever.
+pub(super) fn encode(
+    width: usize,
+    height: usize,
+    palette: &Palette,
+    frames: impl Iterator<Item = (Vec<u8>, u32)>,
+) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(b"GIF89a");
+    push_u16(&mut out, width);
+    push_u16(&mut out, height);
+    // Global color table of 2^(1 + 1) entries, background index 0, square pixels
+    out.extend_from_slice(&[0x80 | (MIN_CODE_SIZE - 1), 0, 0]);
+    for color in palette {
+        out.extend_from_slice(color);
+    }
+    // NETSCAPE2.0 application extension with a loop count of 0 (forever)
+    out.extend_from_slice(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00");
+
+    let mut elapsed = 0;
+    let mut shown_cs = 0;
+    for (colors, duration) in frames {
+        elapsed += duration;
+        let delay = elapsed * 100 / 60 - shown_cs;
+        if delay < MIN_DELAY_CS {
+            continue;
+        }
+        shown_cs += delay;
+
+        // Graphic control extension: no disposal, no transparency
+        out.extend_from_slice(&[0x21, 0xF9, 0x04, 0x00]);
+        push_u16(&mut out, delay as usize);
+        out.extend_from_slice(&[0x00, 0x00]);
+
+        // Image descriptor covering the whole canvas, no local color table
+        out.push(0x2C);
+        push_u16(&mut out, 0);
+        push_u16(&mut out, 0);
+        push_u16(&mut out, width);
+        push_u16(&mut out, height);
+        out.push(0);
+
+        out.push(MIN_CODE_SIZE);
+        for block in compress(&colors).chunks(255) {
+            out.push(block.len() as u8);
+            out.extend_from_slice(block);
+        }
+        out.push(0);
+    }
+
+    out.push(0x3B);
+    out
+}
+
+fn push_u16(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u16).to_le_bytes());
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.acc |= (code as u32) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.acc as u8);
+            self.acc >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.acc as u8);
+        }
+
+        self.bytes
+    }
+}
+
+/// GIF's variable-width LZW, starting over with a clear code whenever the
+/// 12-bit code table fills up.
+fn compress(indices: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << MIN_CODE_SIZE;
+    let end = clear + 1;
+    let mut table = BTreeMap::new();
+    let mut next = end + 1;
+    let mut size = MIN_CODE_SIZE + 1;
+    let mut out = BitWriter {
+        bytes: Vec::new(),
+        acc: 0,
+        bits: 0,
+    };
+
+    out.write(clear, size);
+    let mut prefix: Option<u16> = None;
+    for &index in indices {
+        let Some(code) = prefix else {
+            prefix = Some(index as u16);
+            continue;
+        };
+        if let Some(&longer) = table.get(&(code, index)) {
+            prefix = Some(longer);
+            continue;
+        }
+
+        out.write(code, size);
+        if next == MAX_CODES {
+            out.write(clear, size);
+            table.clear();
+            next = end + 1;
+            size = MIN_CODE_SIZE + 1;
+        } else {
+            table.insert((code, index), next);
+            next += 1;
+            if next > 1 << size && size < 12 {
+                size += 1;
+            }
+        }
+        prefix = Some(index as u16);
+    }
+
+    if let Some(code) = prefix {
+        out.write(code, size);
+    }
+    out.write(end, size);
+
+    out.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    // A plain GIF LZW decoder, growing the code size as the table fills
+    fn decompress(data: &[u8]) -> Vec<u8> {
+        let clear = 1usize << MIN_CODE_SIZE;
+        let end = clear + 1;
+        let reset = || -> Vec<Vec<u8>> { (0..=end).map(|code| vec![code as u8]).collect() };
+        let mut table = reset();
+        let mut size = MIN_CODE_SIZE + 1;
+        let mut pos = 0;
+        let mut prev: Option<Vec<u8>> = None;
+        let mut out = Vec::new();
+        loop {
+            let code = (0..size as usize).fold(0, |code, i| {
+                let bit = (data[(pos + i) / 8] >> ((pos + i) % 8)) & 1;
+                code | (bit as usize) << i
+            });
+            pos += size as usize;
+            if code == clear {
+                table = reset();
+                size = MIN_CODE_SIZE + 1;
+                prev = None;
+                continue;
+            }
+            if code == end {
+                return out;
+            }
+
+            let entry = match (table.get(code), &prev) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(prev)) => [&prev[..], &prev[..1]].concat(),
+                (None, None) => panic!("code {} before any entry", code),
+            };
+            out.extend_from_slice(&entry);
+            if let Some(prev) = prev {
+                if table.len() < MAX_CODES as usize {
+                    table.push([&prev[..], &entry[..1]].concat());
+                }
+            }
+            if table.len() == 1 << size && size < 12 {
+                size += 1;
+            }
+            prev = Some(entry);
+        }
+    }
+
+    #[test]
+    fn lzw_round_trips_short_input() {
+        for input in [&[][..], &[3], &[0, 0, 0, 0, 1, 1, 2, 3, 0, 0]] {
+            assert_eq!(decompress(&compress(input)), input);
+        }
+    }
+
+    #[test]
+    fn lzw_grows_the_code_size_and_resets_past_4095_codes() {
+        let mut seed = 7u32;
+        let indices: Vec<u8> = (0..60_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 28) as u8 & 3
+            })
+            .collect();
+        let compressed = compress(&indices);
+        // Noise this long needs far more than 4096 codes, so the table resets
+        assert!(compressed.len() * 8 / 12 > MAX_CODES as usize);
+        assert_eq!(decompress(&compressed), indices);
+    }
+}
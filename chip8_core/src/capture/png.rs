@@ -0,0 +1,382 @@
+use alloc::{vec, vec::Vec};
+
+use super::{Image, Palette};
+
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const COLOR_RGB: u8 = 2;
+const COLOR_PALETTE: u8 = 3;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut crc = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            crc = if crc & 1 != 0 {
+                0xEDB8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            k += 1;
+        }
+        table[n] = crc;
+        n += 1;
+    }
+
+    table
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        CRC_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn header(out: &mut Vec<u8>, width: usize, height: usize, color_type: u8) {
+    out.extend_from_slice(SIGNATURE);
+    let mut ihdr = Vec::new();
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per sample, deflate, adaptive filtering, no interlace
+    ihdr.extend_from_slice(&[8, color_type, 0, 0, 0]);
+    chunk(out, b"IHDR", &ihdr);
+}
+
+/// Zlib stream of `rows`, each prefixed with filter type 0 (none).
+fn image_data(rows: core::slice::ChunksExact<u8>) -> Vec<u8> {
+    let mut raw = Vec::new();
+    for row in rows {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    zlib(&raw)
+}
+
+pub(super) fn encode_rgb(image: &Image) -> Vec<u8> {
+    let mut out = Vec::new();
+    header(&mut out, image.width, image.height, COLOR_RGB);
+    let data = image_data(image.pixels.chunks_exact(image.width.max(1) * 3));
+    chunk(&mut out, b"IDAT", &data);
+    chunk(&mut out, b"IEND", &[]);
+
+    out
+}
+
+/// Writes `frames` of palette indices, each lasting the given number of 60 Hz
+/// frames, as an APNG that loops forever.
+pub(super) fn encode_apng(
+    width: usize,
+    height: usize,
+    palette: &Palette,
+    frames: impl ExactSizeIterator<Item = (Vec<u8>, u32)>,
+) -> Vec<u8> {
+    let mut out = Vec::new();
+    header(&mut out, width, height, COLOR_PALETTE);
+    chunk(&mut out, b"PLTE", palette.as_flattened());
+
+    let mut actl = Vec::new();
+    actl.extend_from_slice(&(frames.len() as u32).to_be_bytes());
+    actl.extend_from_slice(&0u32.to_be_bytes());
+    chunk(&mut out, b"acTL", &actl);
+
+    let mut sequence = 0u32;
+    for (i, (colors, duration)) in frames.enumerate() {
+        let mut fctl = Vec::new();
+        fctl.extend_from_slice(&sequence.to_be_bytes());
+        for value in [width as u32, height as u32, 0, 0] {
+            fctl.extend_from_slice(&value.to_be_bytes());
+        }
+        // Delay as a fraction of a second, then no disposal and no blending
+        fctl.extend_from_slice(&(duration.min(u16::MAX as u32) as u16).to_be_bytes());
+        fctl.extend_from_slice(&60u16.to_be_bytes());
+        fctl.extend_from_slice(&[0, 0]);
+        chunk(&mut out, b"fcTL", &fctl);
+        sequence += 1;
+
+        let data = image_data(colors.chunks_exact(width.max(1)));
+        if i == 0 {
+            // The first frame doubles as the still image for plain PNG viewers
+            chunk(&mut out, b"IDAT", &data);
+        } else {
+            let mut fdat = sequence.to_be_bytes().to_vec();
+            fdat.extend_from_slice(&data);
+            chunk(&mut out, b"fdAT", &fdat);
+            sequence += 1;
+        }
+    }
+    chunk(&mut out, b"IEND", &[]);
+
+    out
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in bytes {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    (b << 16) | a
+}
+
+fn zlib(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32 KiB window, no preset dictionary
+    let mut out = vec![0x78, 0x01];
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+
+    out
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const WINDOW: usize = 32768;
+const HASH_SIZE: usize = 1 << 12;
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    /// Appends the low `count` bits of `value`, least significant first.
+    fn bits(&mut self, value: u32, count: u8) {
+        self.acc |= value << self.bits;
+        self.bits += count;
+        while self.bits >= 8 {
+            self.bytes.push(self.acc as u8);
+            self.acc >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    /// Appends a Huffman code, which deflate stores most significant bit first.
+    fn code(&mut self, code: u32, len: u8) {
+        self.bits(code.reverse_bits() >> (32 - len), len);
+    }
+
+    /// Symbol from the fixed literal/length code.
+    fn symbol(&mut self, symbol: u16) {
+        let symbol = symbol as u32;
+        match symbol {
+            0..=143 => self.code(0x30 + symbol, 8),
+            144..=255 => self.code(0x190 + symbol - 144, 9),
+            256..=279 => self.code(symbol - 256, 7),
+            _ => self.code(0xC0 + symbol - 280, 8),
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.acc as u8);
+        }
+
+        self.bytes
+    }
+}
+
+/// A single fixed-Huffman block with greedy matches from a one-entry hash
+/// table. Nowhere near optimal, but display frames are mostly long runs, so it
+/// still shrinks them a lot.
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut out = BitWriter {
+        bytes: Vec::new(),
+        acc: 0,
+        bits: 0,
+    };
+    // Final block, fixed Huffman codes
+    out.bits(1, 1);
+    out.bits(1, 2);
+
+    let mut heads = vec![usize::MAX; HASH_SIZE];
+    let hash = |pos: usize| {
+        let key =
+            (data[pos] as usize) << 16 | (data[pos + 1] as usize) << 8 | data[pos + 2] as usize;
+        key.wrapping_mul(2_654_435_761) >> 20 & (HASH_SIZE - 1)
+    };
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut best = (0, 0);
+        if pos + MIN_MATCH <= data.len() {
+            let head = &mut heads[hash(pos)];
+            let candidate = *head;
+            *head = pos;
+            if candidate != usize::MAX && pos - candidate <= WINDOW {
+                let max = (data.len() - pos).min(MAX_MATCH);
+                let len = (0..max)
+                    .take_while(|i| data[candidate + i] == data[pos + i])
+                    .count();
+                if len >= MIN_MATCH {
+                    best = (len, pos - candidate);
+                }
+            }
+        }
+
+        let (len, dist) = best;
+        if len == 0 {
+            out.symbol(data[pos] as u16);
+            pos += 1;
+            continue;
+        }
+
+        let code = LENGTH_BASE
+            .iter()
+            .rposition(|base| *base as usize <= len)
+            .unwrap();
+        out.symbol(257 + code as u16);
+        out.bits(
+            (len - LENGTH_BASE[code] as usize) as u32,
+            LENGTH_EXTRA[code],
+        );
+        let code = DIST_BASE
+            .iter()
+            .rposition(|base| *base as usize <= dist)
+            .unwrap();
+        out.code(code as u32, 5);
+        out.bits((dist - DIST_BASE[code] as usize) as u32, DIST_EXTRA[code]);
+
+        // Index the skipped positions too, so later runs can find them
+        for skipped in pos + 1..(pos + len).min(data.len().saturating_sub(MIN_MATCH - 1)) {
+            heads[hash(skipped)] = skipped;
+        }
+        pos += len;
+    }
+    out.symbol(256);
+
+    out.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Just enough inflate for the single fixed-Huffman block `deflate` writes
+    struct BitReader<'a> {
+        data: &'a [u8],
+        pos: usize,
+    }
+
+    impl BitReader<'_> {
+        fn bit(&mut self) -> u32 {
+            let bit = (self.data[self.pos / 8] >> (self.pos % 8)) & 1;
+            self.pos += 1;
+            bit as u32
+        }
+
+        fn bits(&mut self, count: u8) -> u32 {
+            (0..count).fold(0, |value, i| value | self.bit() << i)
+        }
+
+        fn code(&mut self, len: u8) -> u32 {
+            (0..len).fold(0, |code, _| code << 1 | self.bit())
+        }
+
+        fn symbol(&mut self) -> usize {
+            let mut code = self.code(7);
+            if code <= 0x17 {
+                return 256 + code as usize;
+            }
+            code = code << 1 | self.bit();
+            match code {
+                0x30..=0xBF => return (code - 0x30) as usize,
+                0xC0..=0xC7 => return (280 + code - 0xC0) as usize,
+                _ => {}
+            }
+            code = code << 1 | self.bit();
+            144 + (code - 0x190) as usize
+        }
+    }
+
+    fn inflate(data: &[u8]) -> Vec<u8> {
+        let mut reader = BitReader { data, pos: 0 };
+        assert_eq!(reader.bits(3), 0b011, "one final fixed-Huffman block");
+        let mut out = Vec::new();
+        loop {
+            let symbol = reader.symbol();
+            match symbol {
+                0..=255 => out.push(symbol as u8),
+                256 => return out,
+                _ => {
+                    let code = symbol - 257;
+                    let len = LENGTH_BASE[code] as usize + reader.bits(LENGTH_EXTRA[code]) as usize;
+                    let code = reader.code(5) as usize;
+                    let dist = DIST_BASE[code] as usize + reader.bits(DIST_EXTRA[code]) as usize;
+                    for _ in 0..len {
+                        out.push(out[out.len() - dist]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(adler32(b""), 1);
+    }
+
+    #[test]
+    fn zlib_streams_inflate_to_the_input() {
+        // Long runs like a display, then noise, then a repeat from far back
+        let mut data = vec![0u8; 5000];
+        let mut seed = 1u32;
+        data.extend((0..40_000).map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 24) as u8
+        }));
+        data.extend_from_within(100..2000);
+
+        for input in [&b""[..], b"a", b"abcabcabcabc", &data] {
+            let stream = zlib(input);
+            assert_eq!(stream[..2], [0x78, 0x01]);
+            assert_eq!(u16::from_be_bytes([stream[0], stream[1]]) % 31, 0);
+            let (body, checksum) = stream[2..].split_at(stream.len() - 6);
+            assert_eq!(inflate(body), input);
+            assert_eq!(checksum, adler32(input).to_be_bytes());
+        }
+        assert!(zlib(&[0; 5000]).len() < 100);
+    }
+
+    #[test]
+    fn chunks_end_with_their_crc() {
+        let mut out = Vec::new();
+        chunk(&mut out, b"IEND", &[]);
+        assert_eq!(
+            out,
+            [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]
+        );
+    }
+}
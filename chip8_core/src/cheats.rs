@@ -0,0 +1,112 @@
+use alloc::vec::Vec;
+
+use crate::Machine;
+
+/// A memory or code patch that leaves the ROM image itself untouched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cheat {
+    /// Writes `value` to `addr` at the start of every `run_frame`; the program
+    /// may change it again within the frame.
+    Poke { addr: u16, value: u8 },
+    /// Keeps `addr` at `value` by rewriting it after every instruction, e.g.
+    /// for infinite lives. Freeze the current value with `read8`.
+    Freeze { addr: u16, value: u8 },
+    /// Executes `opcode` whenever the PC reaches `pc`, in place of the
+    /// instruction stored there.
+    ReplaceOpcode { pc: u16, opcode: u16 },
+}
+
+/// Handle returned by `Machine::add_cheat`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CheatId(u32);
+
+#[derive(Debug, Clone)]
+struct Entry {
+    id: CheatId,
+    cheat: Cheat,
+    enabled: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Cheats {
+    entries: Vec<Entry>,
+    next_id: u32,
+}
+
+impl Cheats {
+    fn enabled(&self) -> impl Iterator<Item = Cheat> + '_ {
+        self.entries
+            .iter()
+            .filter(|entry| entry.enabled)
+            .map(|entry| entry.cheat)
+    }
+}
+
+impl Machine {
+    /// Registers `cheat`, enabled. Cheats survive `reset` and apply to
+    /// whichever ROM is loaded.
+    pub fn add_cheat(&mut self, cheat: Cheat) -> CheatId {
+        let id = CheatId(self.cheats.next_id);
+        self.cheats.next_id += 1;
+        self.cheats.entries.push(Entry {
+            id,
+            cheat,
+            enabled: true,
+        });
+
+        id
+    }
+
+    pub fn remove_cheat(&mut self, id: CheatId) {
+        self.cheats.entries.retain(|entry| entry.id != id);
+    }
+
+    pub fn clear_cheats(&mut self) {
+        self.cheats.entries.clear();
+    }
+
+    /// Turns a cheat on or off without forgetting it. Unknown ids are
+    /// ignored.
+    pub fn set_cheat_enabled(&mut self, id: CheatId, enabled: bool) {
+        if let Some(entry) = self.cheats.entries.iter_mut().find(|entry| entry.id == id) {
+            entry.enabled = enabled;
+        }
+    }
+
+    /// Registered cheats in the order they were added, with whether each is
+    /// enabled.
+    pub fn cheats(&self) -> impl Iterator<Item = (CheatId, Cheat, bool)> + '_ {
+        self.cheats
+            .entries
+            .iter()
+            .map(|entry| (entry.id, entry.cheat, entry.enabled))
+    }
+
+    pub(crate) fn apply_pokes(&mut self) {
+        for cheat in self.cheats.enabled() {
+            if let Cheat::Poke { addr, value } = cheat {
+                if let Some(byte) = self.ram.get_mut(addr as usize) {
+                    *byte = value;
+                }
+            }
+        }
+    }
+
+    pub(crate) fn apply_freezes(&mut self) {
+        for cheat in self.cheats.enabled() {
+            if let Cheat::Freeze { addr, value } = cheat {
+                if let Some(byte) = self.ram.get_mut(addr as usize) {
+                    *byte = value;
+                }
+            }
+        }
+    }
+
+    /// The opcode to run instead of the one at `pc`, if any.
+    pub(crate) fn replaced_opcode(&self, pc: u16) -> Option<u16> {
+        self.cheats.enabled().find_map(|cheat| match cheat {
+            Cheat::ReplaceOpcode { pc: at, opcode } if at == pc => Some(opcode),
+            _ => None,
+        })
+    }
+}
@@ -0,0 +1,91 @@
+use alloc::{vec, vec::Vec};
+use core::ops::Range;
+
+use crate::{trace::TraceEvent, Machine};
+
+const EXECUTED: u8 = 1;
+const ACCESSED: u8 = 2;
+
+/// How a run used a stretch of memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryUse {
+    Untouched,
+    /// Fetched as instructions.
+    Code,
+    /// Read or written by instructions, like sprites, fonts or `FX55` saves.
+    Data,
+    /// Both executed and accessed as data, as in self-modifying programs.
+    CodeAndData,
+}
+
+/// A run of adjacent addresses used the same way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemRegion {
+    pub range: Range<usize>,
+    pub usage: MemoryUse,
+}
+
+// One `EXECUTED | ACCESSED` flag set per byte of memory
+#[derive(Debug, Clone)]
+pub(crate) struct Coverage(Vec<u8>);
+
+impl Coverage {
+    fn mark(&mut self, start: usize, len: usize, flag: u8) {
+        let size = self.0.len();
+        for addr in start..start + len {
+            self.0[addr % size] |= flag;
+        }
+    }
+
+    pub(crate) fn record(&mut self, event: &TraceEvent, xo_chip: bool) {
+        match *event {
+            TraceEvent::Executed { pc, opcode } => {
+                let len = if xo_chip && opcode == 0xF000 { 4 } else { 2 };
+                self.mark(pc as usize, len, EXECUTED);
+            }
+            TraceEvent::MemoryRead { addr, len } | TraceEvent::MemoryWrite { addr, len } => {
+                self.mark(addr as usize, len, ACCESSED)
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Machine {
+    /// Starts recording which addresses execute and which instructions read
+    /// or write, discarding earlier records. Loading a ROM does not count.
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage(vec![0; self.ram.len()]));
+    }
+
+    pub fn disable_coverage(&mut self) {
+        self.coverage = None;
+    }
+
+    /// A memory map as recorded since `enable_coverage`, covering all of
+    /// memory in address order; empty while coverage is disabled.
+    pub fn classify_memory(&self) -> Vec<MemRegion> {
+        let Some(Coverage(flags)) = &self.coverage else {
+            return Vec::new();
+        };
+
+        let mut regions: Vec<MemRegion> = Vec::new();
+        for (addr, flag) in flags.iter().enumerate() {
+            let usage = match *flag {
+                0 => MemoryUse::Untouched,
+                EXECUTED => MemoryUse::Code,
+                ACCESSED => MemoryUse::Data,
+                _ => MemoryUse::CodeAndData,
+            };
+            match regions.last_mut() {
+                Some(region) if region.usage == usage => region.range.end = addr + 1,
+                _ => regions.push(MemRegion {
+                    range: addr..addr + 1,
+                    usage,
+                }),
+            }
+        }
+
+        regions
+    }
+}
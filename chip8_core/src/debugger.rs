@@ -0,0 +1,193 @@
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::ops::Range;
+
+use crate::{
+    disasm::Instruction, opcode::Opcode, trace::TraceEvent, Chip8Error, Machine, StopReason,
+};
+
+/// What a single `Machine::step` executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepInfo {
+    pub pc: u16,
+    pub opcode: u16,
+    /// First watched address the instruction wrote to, if any.
+    pub watchpoint: Option<u16>,
+}
+
+impl StepInfo {
+    pub fn instruction(&self) -> Instruction {
+        Instruction::decode(self.opcode)
+    }
+
+    pub fn mnemonic(&self) -> String {
+        self.instruction().to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickResult {
+    Stepped(StepInfo),
+    /// The PC sits on a breakpoint and nothing was executed.
+    BreakpointHit(u16),
+    WatchpointHit {
+        pc: u16,
+        addr: u16,
+    },
+}
+
+impl From<StepInfo> for TickResult {
+    fn from(info: StepInfo) -> Self {
+        match info.watchpoint {
+            Some(addr) => TickResult::WatchpointHit { pc: info.pc, addr },
+            None => TickResult::Stepped(info),
+        }
+    }
+}
+
+/// A subroutine call on the stack, annotated for a backtrace view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallFrame {
+    /// Where `00EE` will continue.
+    pub return_addr: u16,
+    /// Address of the `2NNN` that made the call.
+    pub call_site: u16,
+    /// The subroutine that was called, read back from the call site. `None`
+    /// if memory there no longer holds a call, e.g. in self-modifying code or
+    /// after a `push` from the host.
+    pub target: Option<u16>,
+}
+
+impl Machine {
+    /// Return addresses of the active calls, outermost first. The same as
+    /// `stack`, which a debugger can pair with `call_frames`.
+    pub fn call_stack(&self) -> &[u16] {
+        self.stack()
+    }
+
+    /// `call_stack` with each call site and target, outermost first.
+    pub fn call_frames(&self) -> Vec<CallFrame> {
+        self.stack()
+            .iter()
+            .map(|&return_addr| {
+                let call_site = return_addr.wrapping_sub(2);
+                let target = match self.read16(call_site).map(Opcode::decode) {
+                    Ok(Opcode::Call(addr)) => Some(addr),
+                    _ => None,
+                };
+                CallFrame {
+                    return_addr,
+                    call_site,
+                    target,
+                }
+            })
+            .collect()
+    }
+
+    /// Executes exactly one instruction, ignoring breakpoints.
+    pub fn step(&mut self) -> Result<StepInfo, Chip8Error> {
+        let result = self.execute_step();
+        if let Err(err) = &result {
+            self.report_fault(err);
+        }
+
+        result
+    }
+
+    fn execute_step(&mut self) -> Result<StepInfo, Chip8Error> {
+        // Fetch
+        let pc = self.pc;
+        let op = self.fetch()?;
+        if let Some(hook) = self.trace_hook.as_mut() {
+            hook(pc, op);
+        }
+        // Decode & execute
+        self.watch_hit = None;
+        self.execute(op)?;
+        self.apply_freezes();
+        self.record_profile(pc, op);
+        self.emit(TraceEvent::Executed { pc, opcode: op });
+
+        Ok(StepInfo {
+            pc,
+            opcode: op,
+            watchpoint: self.watch_hit,
+        })
+    }
+
+    /// Like `step`, but stops short of an instruction at a breakpoint. Use
+    /// `step` to move past it.
+    pub fn debug_tick(&mut self) -> Result<TickResult, Chip8Error> {
+        if self.breakpoints.contains(&self.pc) {
+            return Ok(TickResult::BreakpointHit(self.pc));
+        }
+
+        self.step().map(TickResult::from)
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    /// Every breakpoint, in ascending address order.
+    pub fn breakpoints(&self) -> Vec<u16> {
+        self.breakpoints.iter().copied().collect()
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Reports any instruction that writes into `range`, such as `FX33` or
+    /// `FX55`. Loading a ROM does not count.
+    pub fn add_memory_watchpoint(&mut self, range: Range<u16>) {
+        self.watchpoints.push(range);
+    }
+
+    pub fn remove_memory_watchpoint(&mut self, range: Range<u16>) {
+        self.watchpoints.retain(|watch| *watch != range);
+    }
+
+    /// Every watched range, sorted by address.
+    pub fn watchpoints(&self) -> Vec<Range<u16>> {
+        let mut watchpoints = self.watchpoints.clone();
+        watchpoints.sort_by_key(|watch| (watch.start, watch.end));
+        watchpoints
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    /// Ticks until the PC lands on a breakpoint, checked before each fetch, an
+    /// instruction writes to a watchpoint, or `max_cycles` instructions have
+    /// run. A breakpoint at the starting PC is skipped: the first instruction
+    /// always executes so that a run can resume from a breakpoint it stopped
+    /// at.
+    pub fn run_until_breakpoint(&mut self, max_cycles: usize) -> StopReason {
+        for cycle in 0..max_cycles {
+            let result = if cycle == 0 {
+                self.step().map(TickResult::from)
+            } else {
+                self.debug_tick()
+            };
+
+            match result {
+                Ok(TickResult::Stepped(_)) => {}
+                Ok(TickResult::BreakpointHit(pc)) => return StopReason::Breakpoint(pc),
+                Ok(TickResult::WatchpointHit { pc, addr }) => {
+                    return StopReason::Watchpoint { pc, addr }
+                }
+                Err(err) => return StopReason::Error(err),
+            }
+        }
+
+        StopReason::CycleLimit
+    }
+}
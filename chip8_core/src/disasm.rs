@@ -0,0 +1,28 @@
+use alloc::vec::Vec;
+
+use crate::Machine;
+
+pub use crate::opcode::Opcode as Instruction;
+
+pub fn disassemble(op: u16) -> Instruction {
+    Instruction::decode(op)
+}
+
+/// Decodes the whole instructions in `len` bytes of `machine`'s memory from
+/// `start`, returning each one's address. Bytes past the end of memory are
+/// skipped.
+pub fn disassemble_range(machine: &Machine, start: u16, len: usize) -> Vec<(u16, Instruction)> {
+    let start = start as usize;
+    let end = start.saturating_add(len).min(machine.ram.len());
+    machine
+        .ram
+        .get(start..end)
+        .unwrap_or_default()
+        .chunks_exact(2)
+        .enumerate()
+        .map(|(i, bytes)| {
+            let op = ((bytes[0] as u16) << 8) | bytes[1] as u16;
+            ((start + i * 2) as u16, disassemble(op))
+        })
+        .collect()
+}
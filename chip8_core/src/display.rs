@@ -0,0 +1,172 @@
+use alloc::{vec, vec::Vec};
+
+use crate::Machine;
+
+/// Width over height of the display in both resolutions.
+pub const ASPECT_RATIO: f32 = 2.0;
+
+/// RGB colors for the four XO-CHIP plane combinations, indexed like
+/// `Machine::display_colors`. Plain CHIP-8 only uses the first two.
+pub type Palette = [[u8; 3]; 4];
+
+/// Black background, white first plane, greys for the second plane and both.
+pub const DEFAULT_PALETTE: Palette = [[0, 0, 0], [255, 255, 255], [170, 170, 170], [85, 85, 85]];
+
+/// Rows of the display that changed between two `take_display_diff` calls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayDiff {
+    /// `display_generation` at the time of the call.
+    pub generation: u64,
+    /// Indices of changed rows in ascending order. Every row is listed on the
+    /// first call and after a resolution switch.
+    pub rows: Vec<usize>,
+}
+
+impl DisplayDiff {
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
+
+impl Machine {
+    /// Incremented by every instruction or call that may change the display,
+    /// so frontends can skip rendering when it stays the same.
+    pub fn display_generation(&self) -> u64 {
+        self.display_generation
+    }
+
+    /// Compares the display, including both XO-CHIP planes, with how it looked
+    /// at the previous call.
+    pub fn take_display_diff(&mut self) -> DisplayDiff {
+        let (width, height) = self.display_dimensions();
+        let colors = self.display_colors();
+        let rows = if colors.len() == self.diff_base.len() {
+            (0..height)
+                .filter(|y| {
+                    let row = y * width..(y + 1) * width;
+                    colors[row.clone()] != self.diff_base[row]
+                })
+                .collect()
+        } else {
+            (0..height).collect()
+        };
+        self.diff_base = colors;
+
+        DisplayDiff {
+            generation: self.display_generation,
+            rows,
+        }
+    }
+
+    /// One entry per row of the active resolution, with the leftmost pixel in
+    /// the highest used bit: bit 63 in lores, bit 127 in hires.
+    pub fn display_as_packed_rows(&self) -> Vec<u128> {
+        let (width, _) = self.display_dimensions();
+        self.get_display()
+            .chunks_exact(width)
+            .map(|row| {
+                row.iter()
+                    .fold(0u128, |acc, pixel| (acc << 1) | *pixel as u128)
+            })
+            .collect()
+    }
+
+    /// Largest whole-number scale at which the active resolution fits in a
+    /// `width` by `height` area, and at least 1. Whole numbers keep every
+    /// pixel the same size.
+    pub fn scale_hint(&self, width: usize, height: usize) -> usize {
+        let (display_width, display_height) = self.display_dimensions();
+        (width / display_width).min(height / display_height).max(1)
+    }
+
+    /// Writes the display into `out` as RGBA8, 4 bytes per pixel in row-major
+    /// order. Stops early if `out` is shorter than the display.
+    pub fn render_rgba(&self, out: &mut [u8], fg: [u8; 4], bg: [u8; 4]) {
+        for (pixel, rgba) in self.get_display().iter().zip(out.chunks_exact_mut(4)) {
+            rgba.copy_from_slice(if *pixel { &fg } else { &bg });
+        }
+    }
+}
+
+/// Colors and phosphor persistence for a `Renderer`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayStyle {
+    pub palette: Palette,
+    /// Share of the previous frame kept when a pixel gets darker, from 0.0
+    /// (off) to just under 1.0 (very long trails). Brightening is immediate,
+    /// so sprites that XOR-erase and redraw every frame stop flickering.
+    pub persistence: f32,
+}
+
+impl Default for DisplayStyle {
+    fn default() -> Self {
+        Self {
+            palette: DEFAULT_PALETTE,
+            persistence: 0.0,
+        }
+    }
+}
+
+/// Renders the display through a `DisplayStyle`, keeping the previous frame
+/// for phosphor persistence.
+#[derive(Debug, Clone)]
+pub struct Renderer {
+    style: DisplayStyle,
+    glow: Vec<[f32; 3]>,
+}
+
+impl Renderer {
+    pub fn new(style: DisplayStyle) -> Self {
+        Self {
+            style,
+            glow: Vec::new(),
+        }
+    }
+
+    pub fn style(&self) -> &DisplayStyle {
+        &self.style
+    }
+
+    pub fn set_style(&mut self, style: DisplayStyle) {
+        self.style = style;
+    }
+
+    /// Writes the display into `out` as RGBA8 like `Machine::render_rgba`.
+    /// Call once per frame: each call is one step of phosphor decay. Trails
+    /// are dropped when the resolution changes.
+    pub fn render_rgba(&mut self, machine: &Machine, out: &mut [u8]) {
+        let colors = machine.display_colors();
+        if self.glow.len() != colors.len() {
+            self.glow = vec![[0.0; 3]; colors.len()];
+            for (glow, color) in self.glow.iter_mut().zip(&colors) {
+                *glow = self.style.palette[*color as usize].map(f32::from);
+            }
+        }
+
+        let persistence = self.style.persistence.clamp(0.0, 0.99);
+        for ((glow, color), rgba) in self
+            .glow
+            .iter_mut()
+            .zip(&colors)
+            .zip(out.chunks_exact_mut(4))
+        {
+            let target = self.style.palette[*color as usize];
+            for ((channel, target), byte) in glow.iter_mut().zip(target).zip(rgba.iter_mut()) {
+                let target = f32::from(target);
+                *channel = if target >= *channel {
+                    target
+                } else {
+                    target + (*channel - target) * persistence
+                };
+                *byte = *channel as u8;
+            }
+            rgba[3] = 0xFF;
+        }
+    }
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new(DisplayStyle::default())
+    }
+}
@@ -0,0 +1,141 @@
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    rom::{fnv1a_update, FNV_OFFSET},
+    Chip8Error, KeyWait, Machine,
+};
+
+/// How a machine should look after running a fixed number of frames, to
+/// catch regressions in any opcode path the ROM exercises.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Golden {
+    pub frames: usize,
+    pub state_hash: u64,
+    /// `display_as_packed_rows` at the end, to show where a mismatch is.
+    pub display: Vec<u128>,
+}
+
+/// A run that didn't reproduce its golden.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub expected: Golden,
+    pub actual: Golden,
+    /// Display rows that differ, which may be empty when only memory or
+    /// registers do.
+    pub rows: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HarnessError {
+    Machine(Chip8Error),
+    Mismatch(Mismatch),
+}
+
+impl fmt::Display for HarnessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HarnessError::Machine(err) => write!(f, "{}", err),
+            HarnessError::Mismatch(mismatch) => write!(
+                f,
+                "state hash {:#018X} after {} frames, expected {:#018X} ({} display rows differ)",
+                mismatch.actual.state_hash,
+                mismatch.actual.frames,
+                mismatch.expected.state_hash,
+                mismatch.rows.len()
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HarnessError {}
+
+impl From<Chip8Error> for HarnessError {
+    fn from(err: Chip8Error) -> Self {
+        HarnessError::Machine(err)
+    }
+}
+
+impl Machine {
+    /// 64-bit FNV-1a digest of everything `save_state` captures: memory,
+    /// registers, stack, timers, keys, flags, both display planes and the
+    /// random number generator.
+    pub fn state_hash(&self) -> u64 {
+        let key_wait = match self.key_wait {
+            KeyWait::Idle => [0, 0],
+            KeyWait::Waiting => [1, 0],
+            KeyWait::Pressed(key) => [2, key],
+            KeyWait::Released(key) => [3, key],
+        };
+        let (first, second) = self.get_display_planes();
+        let pixels = |plane: &[bool]| plane.iter().map(|pixel| *pixel as u8).collect::<Vec<_>>();
+
+        [
+            &self.pc.to_be_bytes()[..],
+            &self.ram,
+            &pixels(first),
+            &pixels(second),
+            &[self.plane_mask, self.hires as u8],
+            &self.v_reg,
+            &self.i_reg.to_be_bytes(),
+            &self
+                .stack()
+                .iter()
+                .flat_map(|addr| addr.to_be_bytes())
+                .collect::<Vec<_>>(),
+            &self.keys.map(|key| key as u8),
+            &key_wait,
+            &[self.dt, self.st],
+            &self.rpl_flags,
+            &[self.halted as u8],
+            self.audio_pattern
+                .as_ref()
+                .map_or(&[][..], |pattern| &pattern[..]),
+            &self.rng.get_seed(),
+            &self.rng.get_stream().to_be_bytes(),
+            &self.rng.get_word_pos().to_be_bytes(),
+        ]
+        .iter()
+        .fold(FNV_OFFSET, |hash, bytes| fnv1a_update(hash, bytes))
+    }
+
+    /// Runs `frames` frames and captures the result as a golden.
+    pub fn record_golden(&mut self, frames: usize) -> Result<Golden, Chip8Error> {
+        for _ in 0..frames {
+            self.run_frame()?;
+        }
+
+        Ok(Golden {
+            frames,
+            state_hash: self.state_hash(),
+            display: self.display_as_packed_rows(),
+        })
+    }
+
+    /// Runs as many frames as `golden` records and compares the outcome.
+    /// Set up the machine exactly as when the golden was recorded first: same
+    /// quirks, speed, RNG seed and freshly loaded ROM.
+    pub fn check_golden(&mut self, golden: &Golden) -> Result<(), HarnessError> {
+        let actual = self.record_golden(golden.frames)?;
+        if actual == *golden {
+            return Ok(());
+        }
+
+        let rows = if actual.display.len() == golden.display.len() {
+            (0..golden.display.len())
+                .filter(|row| actual.display[*row] != golden.display[*row])
+                .collect()
+        } else {
+            (0..actual.display.len().max(golden.display.len())).collect()
+        };
+        Err(HarnessError::Mismatch(Mismatch {
+            expected: golden.clone(),
+            actual,
+            rows,
+        }))
+    }
+}
@@ -0,0 +1,63 @@
+use alloc::boxed::Box;
+use core::ops::Range;
+
+use crate::Machine;
+
+/// Custom hardware mapped over part of the address space, for example a serial
+/// port or a real-time clock.
+pub trait Peripheral {
+    /// Called when the program reads `addr`, which lies in the mapped range.
+    fn read(&mut self, addr: u16) -> u8;
+    /// Called when the program writes `value` to `addr`.
+    fn write(&mut self, addr: u16, value: u8);
+}
+
+pub(crate) struct Mapping {
+    range: Range<u16>,
+    peripheral: Box<dyn Peripheral>,
+}
+
+impl Machine {
+    /// Routes every access the program makes to `range`, including fetches,
+    /// sprite data and `write8`, to `peripheral` instead of RAM. A later
+    /// mapping overlapping an earlier one takes precedence.
+    ///
+    /// `read8` and `read16` still peek at the RAM underneath, so debuggers
+    /// don't trigger side effects. Peripherals aren't part of `save_state`.
+    pub fn map_io(&mut self, range: Range<u16>, peripheral: impl Peripheral + 'static) {
+        self.io.push(Mapping {
+            range,
+            peripheral: Box::new(peripheral),
+        });
+    }
+
+    /// Removes every peripheral, exposing RAM again.
+    pub fn clear_io(&mut self) {
+        self.io.clear();
+    }
+
+    /// Reads RAM index `index`, as returned by `mem_index`, through the
+    /// peripherals.
+    pub(crate) fn bus_read(&mut self, index: usize) -> u8 {
+        match self.peripheral_at(index) {
+            Some(peripheral) => peripheral.read(index as u16),
+            None => self.ram[index],
+        }
+    }
+
+    pub(crate) fn bus_write(&mut self, index: usize, value: u8) {
+        match self.peripheral_at(index) {
+            Some(peripheral) => peripheral.write(index as u16, value),
+            None => self.ram[index] = value,
+        }
+    }
+
+    fn peripheral_at(&mut self, index: usize) -> Option<&mut dyn Peripheral> {
+        let addr = u16::try_from(index).ok()?;
+        self.io
+            .iter_mut()
+            .rev()
+            .find(|mapping| mapping.range.contains(&addr))
+            .map(|mapping| &mut *mapping.peripheral as &mut dyn Peripheral)
+    }
+}
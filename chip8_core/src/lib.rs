@@ -1,17 +1,91 @@
-use rand::random;
-use std::collections::VecDeque;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod asm;
+pub mod audio;
+pub mod capture;
+pub mod cheats;
+pub mod coverage;
+pub mod debugger;
+pub mod disasm;
+pub mod display;
+pub mod harness;
+pub mod io;
+pub mod lockstep;
+pub mod memio;
+mod memory;
+mod opcode;
+pub mod profile;
+pub mod replay;
+mod rewind;
+pub mod rom;
+mod savestate;
+pub mod storage;
+#[cfg(feature = "std")]
+pub mod thread;
+mod timing;
+pub mod trace;
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeSet, VecDeque},
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use audio::AudioSink;
+use cheats::Cheats;
+use core::{fmt, ops::Range};
+use coverage::Coverage;
+use io::Mapping;
+pub use opcode::Opcode;
+use profile::Profiler;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use replay::InputRecording;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_big_array::BigArray;
+use storage::Storage;
+use timing::Clock;
+use trace::{EventHook, FaultHook, TraceEvent};
 
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
+pub const HIRES_SCREEN_WIDTH: usize = 128;
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
+pub const PACKED_DISPLAY_SIZE: usize = HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT / 8;
+
+const SCREEN_BUFFER_SIZE: usize = HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT;
+const SCROLL_STEP: usize = 4;
+// A 16x16 sprite for each of the two XO-CHIP planes
+const MAX_SPRITE_BYTES: usize = 2 * 32;
 
 const START_ADDR: u16 = 0x200;
 
 const RAM_SIZE: usize = 4096;
+const XO_RAM_SIZE: usize = 0x10000;
 const NUM_REGS: usize = 16;
 const STACK_SIZE: usize = 16;
+const VIP_STACK_SIZE: usize = 12;
 const NUM_KEYS: usize = 16;
+pub const NUM_RPL_FLAGS: usize = 8;
 
-const FONTSET_SIZE: usize = 80;
+pub const FONT_GLYPH_SIZE: usize = 5;
+pub const BIG_FONT_GLYPH_SIZE: usize = 10;
+
+const TIMER_HZ: u32 = 60;
+const DEFAULT_CYCLES_PER_FRAME: usize = 10;
+const BEEP_HZ: u32 = 440;
+const BEEP_AMPLITUDE: f32 = 0.25;
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+const AUDIO_PATTERN_HZ: u32 = 4000;
+
+const FONT_ADDR: usize = 0;
+pub const FONTSET_SIZE: usize = 80;
 const FONTSET: [u8; FONTSET_SIZE] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
@@ -31,16 +105,447 @@ const FONTSET: [u8; FONTSET_SIZE] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+const BIG_FONT_ADDR: usize = FONT_ADDR + FONTSET_SIZE;
+pub const BIG_FONTSET_SIZE: usize = 160;
+const BIG_FONTSET: [u8; BIG_FONTSET_SIZE] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+/// Address of the built-in glyph for the hex digit in the low nibble of `digit`,
+/// as long as the font has not been moved with `Machine::set_font`.
+pub fn font_offset(digit: u8) -> u16 {
+    (FONT_ADDR + (digit & 0x0F) as usize * FONT_GLYPH_SIZE) as u16
+}
+
+/// Address of the SUPER-CHIP 8x10 glyph used by `FX30` for the low nibble of
+/// `digit`, as long as the font has not been moved with `Machine::set_big_font`.
+pub fn big_font_offset(digit: u8) -> u16 {
+    (BIG_FONT_ADDR + (digit & 0x0F) as usize * BIG_FONT_GLYPH_SIZE) as u16
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Error {
+    StackUnderflow,
+    StackOverflow,
+    InvalidOpcode(u16),
+    PcOutOfBounds {
+        pc: u16,
+    },
+    MemoryOutOfBounds {
+        addr: usize,
+    },
+    RomTooLarge {
+        size: usize,
+        max: usize,
+    },
+    /// A saved state with `ram_len` bytes of memory can't be restored into a
+    /// machine with `expected`.
+    StateMismatch {
+        ram_len: usize,
+        expected: usize,
+    },
+    #[cfg(feature = "std")]
+    Io(std::io::ErrorKind),
+    /// The `EmulatorThread` panicked before it could report whether the ROM
+    /// loaded.
+    #[cfg(feature = "std")]
+    ThreadPanicked,
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::StackUnderflow => write!(f, "return with an empty stack"),
+            Chip8Error::StackOverflow => write!(f, "call nested deeper than the stack allows"),
+            Chip8Error::InvalidOpcode(op) => write!(f, "invalid opcode {:#06X}", op),
+            Chip8Error::PcOutOfBounds { pc } => {
+                write!(f, "program counter {:#06X} is outside memory", pc)
+            }
+            Chip8Error::MemoryOutOfBounds { addr } => {
+                write!(f, "memory access at {:#06X} is out of bounds", addr)
+            }
+            Chip8Error::RomTooLarge { size, max } => {
+                write!(f, "ROM needs {} bytes but only {} are available", size, max)
+            }
+            Chip8Error::StateMismatch { ram_len, expected } => write!(
+                f,
+                "saved state has {} bytes of memory but the machine has {}",
+                ram_len, expected
+            ),
+            #[cfg(feature = "std")]
+            Chip8Error::Io(kind) => write!(f, "unable to read ROM: {}", kind),
+            #[cfg(feature = "std")]
+            Chip8Error::ThreadPanicked => write!(f, "emulator thread panicked"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Chip8Error {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunResult {
+    DisplayChanged { cycles: usize },
+    Breakpoint(u16),
+    CycleLimit,
+    Error(Chip8Error),
+}
+
+/// How a `run_frame` call went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameOutcome {
+    /// Instructions executed, fewer than the speed if the frame ended early.
+    pub cycles: usize,
+    pub waiting_for_key: bool,
+    pub halted: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint(u16),
+    Watchpoint { pc: u16, addr: u16 },
+    CycleLimit,
+    Error(Chip8Error),
+}
+
+/// Toggles for opcodes that CHIP-8 implementations disagree on. The default
+/// matches this crate's original behavior.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8XY6`/`8XYE` copy VY into VX before shifting (COSMAC VIP).
+    pub shift_uses_vy: bool,
+    /// `BXNN` jumps to XNN + VX instead of NNN + V0 (CHIP-48 and SUPER-CHIP).
+    pub jump_offset_uses_vx: bool,
+    /// `FX55`/`FX65` leave I pointing past the last register (COSMAC VIP).
+    pub load_store_increments_i: bool,
+    /// `FX1E` sets VF to 1 when I ends up past 0xFFF and to 0 otherwise (the
+    /// Amiga interpreter, which a few ROMs rely on).
+    pub add_index_sets_vf: bool,
+    /// `DXYN` drops pixels past the right and bottom edges instead of
+    /// wrapping them around. The starting position still wraps.
+    pub clip_sprites: bool,
+    /// `DXYN` waits for the next frame, so `run_frame` ends right after a
+    /// draw (COSMAC VIP).
+    pub display_wait: bool,
+    /// `00FE`/`00FF` clear the display when switching resolution. SUPER-CHIP
+    /// 1.1 leaves the old pixels in place.
+    pub mode_switch_clears: bool,
+    /// Memory accesses past the end wrap around to address 0 instead of
+    /// failing with `MemoryOutOfBounds`.
+    pub wrap_memory: bool,
+    /// `run_frame` spends an approximate COSMAC VIP machine-cycle budget per
+    /// frame instead of a fixed instruction count, and draws wait for the
+    /// next frame as with `display_wait`.
+    pub vip_timing: bool,
+    /// Enables the XO-CHIP extensions: the second bit plane selected by
+    /// `FN01`, `F000 NNNN` long index loads that skips step over, the `F002`
+    /// audio pattern and a 64 KiB address space.
+    pub xo_chip: bool,
+    /// How deep `2NNN` calls may nest.
+    pub stack_limit: StackLimit,
+    /// What unassigned opcodes such as `0NNN`, `8XY8` or `EXFF` do.
+    pub invalid_opcodes: InvalidOpcodePolicy,
+}
+
+/// How an opcode with no meaning under the current quirks is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidOpcodePolicy {
+    /// Skip it like a no-op, as most interpreters do with `0NNN` machine
+    /// code calls.
+    #[default]
+    Ignore,
+    /// Skip it, but report a `TraceEvent::InvalidOpcode` to the event hook.
+    Log,
+    /// Fail with `InvalidOpcode`, for catching decoding or assembly bugs.
+    Error,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            jump_offset_uses_vx: false,
+            load_store_increments_i: false,
+            add_index_sets_vf: false,
+            clip_sprites: false,
+            display_wait: false,
+            mode_switch_clears: true,
+            wrap_memory: false,
+            vip_timing: false,
+            xo_chip: false,
+            stack_limit: StackLimit::default(),
+            invalid_opcodes: InvalidOpcodePolicy::Ignore,
+        }
+    }
+}
+
+/// Call depth at which `2NNN` fails with `StackOverflow`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackLimit {
+    /// At most this many return addresses: 12 on the COSMAC VIP, 16 on
+    /// SUPER-CHIP and by default.
+    Levels(usize),
+    /// No limit, for homebrew that recurses deeply. A runaway recursion keeps
+    /// growing the stack until memory runs out.
+    Unlimited,
+}
+
+impl Default for StackLimit {
+    fn default() -> Self {
+        StackLimit::Levels(STACK_SIZE)
+    }
+}
+
+/// Quirk sets matching the interpreters most ROMs were written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompatibilityProfile {
+    /// The original COSMAC VIP interpreter.
+    CosmacVip,
+    /// SUPER-CHIP 1.1 on the HP48.
+    SuperChip,
+    /// Octo's XO-CHIP.
+    XoChip,
+}
+
+impl From<CompatibilityProfile> for Quirks {
+    fn from(profile: CompatibilityProfile) -> Self {
+        match profile {
+            CompatibilityProfile::CosmacVip => Quirks {
+                shift_uses_vy: true,
+                jump_offset_uses_vx: false,
+                load_store_increments_i: true,
+                add_index_sets_vf: false,
+                clip_sprites: true,
+                display_wait: true,
+                mode_switch_clears: true,
+                wrap_memory: true,
+                vip_timing: true,
+                xo_chip: false,
+                stack_limit: StackLimit::Levels(VIP_STACK_SIZE),
+                invalid_opcodes: InvalidOpcodePolicy::Ignore,
+            },
+            CompatibilityProfile::SuperChip => Quirks {
+                shift_uses_vy: false,
+                jump_offset_uses_vx: true,
+                load_store_increments_i: false,
+                add_index_sets_vf: false,
+                clip_sprites: true,
+                display_wait: false,
+                mode_switch_clears: false,
+                wrap_memory: false,
+                vip_timing: false,
+                xo_chip: false,
+                stack_limit: StackLimit::Levels(STACK_SIZE),
+                invalid_opcodes: InvalidOpcodePolicy::Ignore,
+            },
+            CompatibilityProfile::XoChip => Quirks {
+                shift_uses_vy: true,
+                jump_offset_uses_vx: false,
+                load_store_increments_i: true,
+                add_index_sets_vf: false,
+                clip_sprites: false,
+                display_wait: false,
+                mode_switch_clears: true,
+                wrap_memory: true,
+                vip_timing: false,
+                xo_chip: true,
+                stack_limit: StackLimit::Levels(STACK_SIZE),
+                invalid_opcodes: InvalidOpcodePolicy::Ignore,
+            },
+        }
+    }
+}
+
+// Keeps the picture when the resolution changes: going up each lores pixel
+// becomes a 2x2 block, going down every other pixel of every other row is kept
+fn rescale_plane(plane: &[bool; SCREEN_BUFFER_SIZE], to_hires: bool) -> [bool; SCREEN_BUFFER_SIZE] {
+    let mut scaled = [false; SCREEN_BUFFER_SIZE];
+    if to_hires {
+        for (i, pixel) in scaled.iter_mut().enumerate() {
+            let (x, y) = (i % HIRES_SCREEN_WIDTH, i / HIRES_SCREEN_WIDTH);
+            *pixel = plane[(y / 2) * SCREEN_WIDTH + x / 2];
+        }
+    } else {
+        for (i, pixel) in scaled[..SCREEN_WIDTH * SCREEN_HEIGHT]
+            .iter_mut()
+            .enumerate()
+        {
+            let (x, y) = (i % SCREEN_WIDTH, i / SCREEN_WIDTH);
+            *pixel = plane[2 * y * HIRES_SCREEN_WIDTH + 2 * x];
+        }
+    }
+
+    scaled
+}
+
+/// Progress of an `FX0A` wait, which completes when a key is released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum KeyWait {
+    Idle,
+    Waiting,
+    Pressed(u8),
+    Released(u8),
+}
+
+/// Everything needed to resume a machine exactly where it was captured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MachineState {
+    pc: u16,
+    ram: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(with = "BigArray"))]
+    screen: [bool; SCREEN_BUFFER_SIZE],
+    #[cfg_attr(feature = "serde", serde(with = "BigArray"))]
+    second_plane: [bool; SCREEN_BUFFER_SIZE],
+    plane_mask: u8,
+    hires: bool,
+    v_reg: [u8; NUM_REGS],
+    i_reg: u16,
+    stack: Vec<u16>,
+    keys: [bool; NUM_KEYS],
+    key_wait: KeyWait,
+    dt: u8,
+    st: u8,
+    rpl_flags: [u8; NUM_RPL_FLAGS],
+    halted: bool,
+    audio_pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    rng: RngState,
+}
+
+/// Position of the `CXNN` generator, so restored machines draw the same
+/// numbers as the original.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct RngState {
+    seed: [u8; 32],
+    stream: u64,
+    word_pos: u128,
+}
+
+impl RngState {
+    fn capture(rng: &ChaCha12Rng) -> Self {
+        Self {
+            seed: rng.get_seed(),
+            stream: rng.get_stream(),
+            word_pos: rng.get_word_pos(),
+        }
+    }
+
+    fn rng(&self) -> ChaCha12Rng {
+        let mut rng = ChaCha12Rng::from_seed(self.seed);
+        rng.set_stream(self.stream);
+        rng.set_word_pos(self.word_pos);
+        rng
+    }
+}
+
+pub type RplFlagsHook = Box<dyn FnMut(&[u8; NUM_RPL_FLAGS])>;
+
 pub struct Machine {
     pc: u16,
-    ram: [u8; RAM_SIZE],
-    screen: [bool; SCREEN_WIDTH * SCREEN_HEIGHT],
+    ram: Vec<u8>,
+    screen: [bool; SCREEN_BUFFER_SIZE],
+    second_plane: [bool; SCREEN_BUFFER_SIZE],
+    plane_mask: u8,
+    hires: bool,
+    screen_dirty: bool,
+    display_generation: u64,
+    diff_base: Vec<u8>,
     v_reg: [u8; NUM_REGS],
     i_reg: u16,
-    stack: VecDeque<u16>,
+    stack: Vec<u16>,
+    max_stack_depth: usize,
     keys: [bool; NUM_KEYS],
+    key_wait: KeyWait,
     dt: u8,
     st: u8,
+    rpl_flags: [u8; NUM_RPL_FLAGS],
+    halted: bool,
+    audio_pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    // Samples `fill_audio` has produced so far
+    audio_phase: u64,
+    display_history: Vec<[u8; PACKED_DISPLAY_SIZE]>,
+    display_history_depth: usize,
+    rewind_buffer: VecDeque<MachineState>,
+    rewind_capacity: usize,
+    recording: Option<InputRecording>,
+    sound_handler: Option<Box<dyn FnMut(bool)>>,
+    audio_sink: Option<Box<dyn AudioSink>>,
+    storage: Option<Box<dyn Storage>>,
+    io: Vec<Mapping>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    cheats: Cheats,
+    clock: Clock,
+    trace_hook: Option<Box<dyn FnMut(u16, u16)>>,
+    rpl_flags_hook: Option<RplFlagsHook>,
+    event_hook: Option<EventHook>,
+    fault_hook: Option<FaultHook>,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Range<u16>>,
+    watch_hit: Option<u16>,
+    font: [u8; FONTSET_SIZE],
+    font_addr: u16,
+    big_font: [u8; BIG_FONTSET_SIZE],
+    big_font_addr: u16,
+    quirks: Quirks,
+    cycles_per_frame: usize,
+    // The algorithm behind rand's `StdRng`, used directly so its position
+    // can be saved
+    rng: ChaCha12Rng,
+}
+
+/// Decodes `data` as a program loaded at the usual start address, returning
+/// each instruction's address and mnemonic.
+pub fn disassemble(data: &[u8]) -> Vec<(u16, String)> {
+    data.chunks(2)
+        .enumerate()
+        .map(|(i, bytes)| {
+            let addr = START_ADDR.wrapping_add((i * 2) as u16);
+            let text = match *bytes {
+                [high, low] => Opcode::decode(((high as u16) << 8) | low as u16).to_string(),
+                [byte] => format!("DB {:#04X}", byte),
+                _ => unreachable!(),
+            };
+
+            (addr, text)
+        })
+        .collect()
+}
+
+fn ram_size(quirks: &Quirks) -> usize {
+    if quirks.xo_chip {
+        XO_RAM_SIZE
+    } else {
+        RAM_SIZE
+    }
+}
+
+#[cfg(feature = "std")]
+fn default_rng() -> ChaCha12Rng {
+    ChaCha12Rng::from_entropy()
+}
+
+// Without std there is no entropy source; use `Machine::with_seed` to vary it.
+#[cfg(not(feature = "std"))]
+fn default_rng() -> ChaCha12Rng {
+    ChaCha12Rng::seed_from_u64(0)
 }
 
 impl Default for Machine {
@@ -51,57 +556,392 @@ impl Default for Machine {
 
 impl Machine {
     pub fn new() -> Self {
+        Self::new_with_quirks(Quirks::default())
+    }
+
+    /// Creates a machine whose `CXNN` results are fully determined by `seed`.
+    pub fn with_seed(seed: u64) -> Self {
+        let mut machine = Self::new();
+        machine.set_rng_seed(seed);
+        machine
+    }
+
+    /// Reseeds the `CXNN` generator, e.g. before replaying recorded input.
+    pub fn set_rng_seed(&mut self, seed: u64) {
+        self.rng = ChaCha12Rng::seed_from_u64(seed);
+    }
+
+    pub fn new_with_quirks(quirks: Quirks) -> Self {
         let mut new_machine = Self {
             pc: START_ADDR,
-            ram: [0; RAM_SIZE],
-            screen: [false; SCREEN_WIDTH * SCREEN_HEIGHT],
+            ram: vec![0; ram_size(&quirks)],
+            screen: [false; SCREEN_BUFFER_SIZE],
+            second_plane: [false; SCREEN_BUFFER_SIZE],
+            plane_mask: 1,
+            hires: false,
+            screen_dirty: true,
+            display_generation: 0,
+            diff_base: Vec::new(),
             v_reg: [0; NUM_REGS],
             i_reg: 0,
-            stack: VecDeque::with_capacity(STACK_SIZE),
+            stack: Vec::new(),
+            max_stack_depth: 0,
             keys: [false; NUM_KEYS],
+            key_wait: KeyWait::Idle,
             dt: 0,
             st: 0,
+            rpl_flags: [0; NUM_RPL_FLAGS],
+            halted: false,
+            audio_pattern: None,
+            audio_phase: 0,
+            display_history: Vec::new(),
+            display_history_depth: 0,
+            rewind_buffer: VecDeque::new(),
+            rewind_capacity: 0,
+            recording: None,
+            sound_handler: None,
+            audio_sink: None,
+            storage: None,
+            io: Vec::new(),
+            profiler: None,
+            coverage: None,
+            cheats: Cheats::default(),
+            clock: Clock::default(),
+            trace_hook: None,
+            rpl_flags_hook: None,
+            event_hook: None,
+            fault_hook: None,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            font: FONTSET,
+            font_addr: FONT_ADDR as u16,
+            big_font: BIG_FONTSET,
+            big_font_addr: BIG_FONT_ADDR as u16,
+            quirks,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            rng: default_rng(),
         };
 
-        new_machine.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+        new_machine.load_fonts();
 
         new_machine
     }
 
-    pub fn load(&mut self, data: &[u8]) {
-        let start = START_ADDR as usize;
-        let end = START_ADDR as usize + data.len();
+    pub fn load(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        self.load_chunk(0, data)
+    }
+
+    /// Reads the file at `path` and loads it like `load`.
+    #[cfg(feature = "std")]
+    pub fn load_from_path<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<(), Chip8Error> {
+        let data = std::fs::read(path).map_err(|err| Chip8Error::Io(err.kind()))?;
+        self.load(&data)
+    }
+
+    /// Writes `data` at `offset` bytes past the program start, so a ROM can be
+    /// assembled piece by piece before execution begins.
+    pub fn load_chunk(&mut self, offset: usize, data: &[u8]) -> Result<(), Chip8Error> {
+        let max = self.ram.len() - START_ADDR as usize;
+        let size = offset.saturating_add(data.len());
+        if size > max {
+            return Err(Chip8Error::RomTooLarge { size, max });
+        }
+
+        let start = START_ADDR as usize + offset;
+        let end = start + data.len();
         self.ram[start..end].copy_from_slice(data);
+
+        Ok(())
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Switching `xo_chip` grows or truncates memory to match and drops the
+    /// rewind history. Memory only moves when it grows beyond the largest
+    /// size it has had.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        if self.ram.len() != ram_size(&quirks) {
+            self.ram.resize(ram_size(&quirks), 0);
+            self.rewind_buffer.clear();
+        }
     }
 
+    /// Pixels of the active resolution, row-major with `display_dimensions().0`
+    /// pixels per row.
     pub fn get_display(&self) -> &[bool] {
-        &self.screen
+        let (width, height) = self.display_dimensions();
+        &self.screen[..width * height]
+    }
+
+    /// Both XO-CHIP bit planes at the active resolution. Without XO-CHIP only
+    /// the first plane is ever drawn to.
+    pub fn get_display_planes(&self) -> (&[bool], &[bool]) {
+        let (width, height) = self.display_dimensions();
+        (
+            &self.screen[..width * height],
+            &self.second_plane[..width * height],
+        )
+    }
+
+    /// Per-pixel color index 0-3, with the first plane as bit 0 and the second
+    /// plane as bit 1.
+    pub fn display_colors(&self) -> Vec<u8> {
+        let (first, second) = self.get_display_planes();
+        first
+            .iter()
+            .zip(second)
+            .map(|(a, b)| *a as u8 | (*b as u8) << 1)
+            .collect()
+    }
+
+    /// Width and height of the active resolution.
+    pub fn display_dimensions(&self) -> (usize, usize) {
+        if self.hires {
+            (HIRES_SCREEN_WIDTH, HIRES_SCREEN_HEIGHT)
+        } else {
+            (SCREEN_WIDTH, SCREEN_HEIGHT)
+        }
+    }
+
+    /// Whether the display may have changed since the last
+    /// `clear_display_dirty`, so frontends can skip re-uploading it.
+    pub fn is_display_dirty(&self) -> bool {
+        self.screen_dirty
+    }
+
+    pub fn clear_display_dirty(&mut self) {
+        self.screen_dirty = false;
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Switches to the 128x64 SUPER-CHIP resolution or back to 64x32, like
+    /// `00FF` and `00FE`. Both planes are cleared, or with the
+    /// `mode_switch_clears` quirk off scaled to the new resolution.
+    pub fn set_hires(&mut self, hires: bool) {
+        if self.quirks.mode_switch_clears {
+            self.screen = [false; SCREEN_BUFFER_SIZE];
+            self.second_plane = [false; SCREEN_BUFFER_SIZE];
+        } else if hires != self.hires {
+            for plane in [&mut self.screen, &mut self.second_plane] {
+                *plane = rescale_plane(plane, hires);
+            }
+        }
+        self.hires = hires;
+        self.display_changed();
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn set_program_counter(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn registers(&self) -> &[u8; NUM_REGS] {
+        &self.v_reg
+    }
+
+    /// Register V`idx`; panics if `idx` is above 0xF.
+    pub fn v(&self, idx: usize) -> u8 {
+        self.v_reg[idx]
+    }
+
+    pub fn set_v(&mut self, idx: usize, val: u8) {
+        self.v_reg[idx] = val;
+    }
+
+    pub fn index_register(&self) -> u16 {
+        self.i_reg
+    }
+
+    pub fn set_index_register(&mut self, i: u16) {
+        self.i_reg = i;
+    }
+
+    /// The whole address space, 4 KiB or 64 KiB with XO-CHIP.
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    pub fn keys(&self) -> &[bool; NUM_KEYS] {
+        &self.keys
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.dt
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.dt = value;
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.st
+    }
+
+    /// Return addresses currently on the stack, oldest call first.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn stack_snapshot(&self) -> Vec<u16> {
+        self.stack().to_vec()
+    }
+
+    pub fn snapshot(&self) -> MachineState {
+        MachineState {
+            pc: self.pc,
+            ram: self.ram.clone(),
+            screen: self.screen,
+            second_plane: self.second_plane,
+            plane_mask: self.plane_mask,
+            hires: self.hires,
+            v_reg: self.v_reg,
+            i_reg: self.i_reg,
+            stack: self.stack_snapshot(),
+            keys: self.keys,
+            key_wait: self.key_wait,
+            dt: self.dt,
+            st: self.st,
+            rpl_flags: self.rpl_flags,
+            halted: self.halted,
+            audio_pattern: self.audio_pattern,
+            rng: RngState::capture(&self.rng),
+        }
+    }
+
+    /// Puts the machine back in `state`, which must come from a machine with
+    /// the same memory size, so 4 KiB or XO-CHIP's 64 KiB. Memory is copied
+    /// into the existing buffer, keeping pointers from `ram_mut` valid.
+    pub fn restore(&mut self, state: MachineState) -> Result<(), Chip8Error> {
+        if state.ram.len() != self.ram.len() {
+            return Err(Chip8Error::StateMismatch {
+                ram_len: state.ram.len(),
+                expected: self.ram.len(),
+            });
+        }
+
+        self.pc = state.pc;
+        self.ram.copy_from_slice(&state.ram);
+        self.screen = state.screen;
+        self.second_plane = state.second_plane;
+        self.plane_mask = state.plane_mask;
+        self.hires = state.hires;
+        self.display_changed();
+        self.v_reg = state.v_reg;
+        self.i_reg = state.i_reg;
+        self.stack = state.stack;
+        self.keys = state.keys;
+        self.key_wait = state.key_wait;
+        self.dt = state.dt;
+        self.set_sound_timer(state.st);
+        self.rpl_flags = state.rpl_flags;
+        self.halted = state.halted;
+        self.audio_pattern = state.audio_pattern;
+        self.rng = state.rng.rng();
+
+        Ok(())
+    }
+
+    /// Captures a quick-save slot; equivalent to `snapshot`.
+    pub fn save_state(&self) -> MachineState {
+        self.snapshot()
+    }
+
+    /// Resumes from a slot that may be loaded again later, failing like
+    /// `restore`.
+    pub fn load_state(&mut self, state: &MachineState) -> Result<(), Chip8Error> {
+        self.restore(state.clone())
     }
 
     pub fn reset(&mut self) {
         self.pc = START_ADDR;
-        self.ram = [0; RAM_SIZE];
-        self.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
-        self.screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
+        self.ram.fill(0);
+        self.load_fonts();
+        self.screen = [false; SCREEN_BUFFER_SIZE];
+        self.second_plane = [false; SCREEN_BUFFER_SIZE];
+        self.plane_mask = 1;
+        self.hires = false;
+        self.display_changed();
         self.v_reg = [0; NUM_REGS];
         self.i_reg = 0;
-        self.stack = VecDeque::with_capacity(STACK_SIZE);
+        self.stack.clear();
+        self.max_stack_depth = 0;
         self.keys = [false; NUM_KEYS];
+        self.key_wait = KeyWait::Idle;
         self.dt = 0;
-        self.st = 0;
+        self.set_sound_timer(0);
+        self.halted = false;
+        self.audio_pattern = None;
+        self.audio_phase = 0;
+        self.display_history.clear();
+        self.rewind_buffer.clear();
+        self.clock = Clock::default();
     }
 
-    pub fn push(&mut self, val: u16) {
-        self.stack.push_back(val);
+    /// Keeps the last `depth` displays, packed one bit per pixel and captured
+    /// on every `tick_timers`. A depth of 0 disables the history.
+    pub fn set_display_history_depth(&mut self, depth: usize) {
+        self.display_history_depth = depth;
+        if self.display_history.len() > depth {
+            let excess = self.display_history.len() - depth;
+            self.display_history.drain(..excess);
+        }
     }
 
-    pub fn pop(&mut self) -> u16 {
-        if let Some(i) = self.stack.pop_back() {
-            i
-        } else {
-            println!("Error: stack is empty");
-            std::process::exit(5);
+    /// Recorded frames, oldest first. Each byte holds 8 pixels of the active
+    /// resolution, MSB leftmost; lores frames only fill the first quarter.
+    pub fn display_history(&self) -> &[[u8; PACKED_DISPLAY_SIZE]] {
+        &self.display_history
+    }
+
+    fn packed_display(&self) -> [u8; PACKED_DISPLAY_SIZE] {
+        let mut packed = [0; PACKED_DISPLAY_SIZE];
+        for (i, pixel) in self.get_display().iter().enumerate() {
+            if *pixel {
+                packed[i / 8] |= 0b1000_0000 >> (i % 8);
+            }
+        }
+
+        packed
+    }
+
+    /// Number of occupied stack slots, i.e. the index the next `push` writes to.
+    pub fn stack_pointer(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Deepest the stack has been since the last `reset`.
+    pub fn max_stack_depth(&self) -> usize {
+        self.max_stack_depth
+    }
+
+    pub fn push(&mut self, val: u16) -> Result<(), Chip8Error> {
+        if let StackLimit::Levels(levels) = self.quirks.stack_limit {
+            if self.stack.len() >= levels {
+                return Err(Chip8Error::StackOverflow);
+            }
         }
+        self.stack.push(val);
+        self.max_stack_depth = self.max_stack_depth.max(self.stack.len());
+
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Result<u16, Chip8Error> {
+        self.stack.pop().ok_or(Chip8Error::StackUnderflow)
     }
 
     pub fn tick_timers(&mut self) {
@@ -110,181 +950,1703 @@ impl Machine {
         }
 
         if self.st > 0 {
-            if self.st == 1 {
-                // BEEP
+            self.set_sound_timer(self.st - 1);
+        }
+
+        if self.display_history_depth > 0 {
+            if self.display_history.len() == self.display_history_depth {
+                self.display_history.remove(0);
             }
-            self.st -= 1;
+            let frame = self.packed_display();
+            self.display_history.push(frame);
         }
+        self.checkpoint();
     }
 
-    pub fn keypress(&mut self, index: usize, pressed: bool) {
-        self.keys[index] = pressed;
+    /// Registers a callback fired on the sound timer's edges: `true` when it
+    /// starts running and `false` when it reaches zero. Frontends that would
+    /// rather poll once per frame can use `is_beeping` instead.
+    pub fn set_sound_handler(&mut self, handler: Box<dyn FnMut(bool)>) {
+        self.sound_handler = Some(handler);
     }
 
-    pub fn tick(&mut self) {
-        // Fetch
-        let op = self.fetch();
-        // Decode & execute
-        self.execute(op);
+    /// Registers a callback receiving `(pc, opcode)` for every instruction,
+    /// called after it is fetched and before it executes.
+    pub fn set_trace_hook(&mut self, hook: Box<dyn FnMut(u16, u16)>) {
+        self.trace_hook = Some(hook);
     }
 
-    fn fetch(&mut self) -> u16 {
-        let higher_byte = self.ram[self.pc as usize] as u16;
-        let lower_byte = self.ram[(self.pc + 1) as usize] as u16;
-        self.pc += 2;
+    /// SUPER-CHIP `FX75`/`FX85` user flags. Like the HP48 originals they
+    /// survive `reset`.
+    pub fn rpl_flags(&self) -> &[u8; NUM_RPL_FLAGS] {
+        &self.rpl_flags
+    }
 
-        (higher_byte << 8) | lower_byte
+    /// Restores flags saved from an earlier session.
+    pub fn set_rpl_flags(&mut self, flags: [u8; NUM_RPL_FLAGS]) {
+        self.rpl_flags = flags;
     }
 
-    fn execute(&mut self, op: u16) {
-        let byte1 = (op & 0xF000) >> 12;
-        let byte2 = (op & 0x0F00) >> 8;
-        let byte3 = (op & 0x00F0) >> 4;
-        let byte4 = op & 0x000F;
+    /// Registers a callback receiving the flags every time `FX75` stores
+    /// them, so a frontend can persist them.
+    pub fn set_rpl_flags_hook(&mut self, hook: RplFlagsHook) {
+        self.rpl_flags_hook = Some(hook);
+    }
 
-        match (byte1, byte2, byte3, byte4) {
-            (0, 0, 0xE, 0) => self.screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT],
-            (0, 0, 0xE, 0xE) => self.pc = self.pop(),
-            (1, _, _, _) => self.pc = op & 0xFFF,
-            (2, _, _, _) => {
-                self.push(self.pc);
-                self.pc = op & 0xFFF
-            }
-            (3, _, _, _) => {
-                if self.v_reg[byte2 as usize] == (op & 0xFF) as u8 {
-                    self.pc += 2
-                }
-            }
-            (4, _, _, _) => {
-                if self.v_reg[byte2 as usize] != (op & 0xFF) as u8 {
-                    self.pc += 2
-                }
-            }
-            (5, _, _, 0) => {
-                if self.v_reg[byte2 as usize] == self.v_reg[byte3 as usize] {
-                    self.pc += 2
-                }
-            }
-            (6, _, _, _) => self.v_reg[byte2 as usize] = (op & 0xFF) as u8,
-            (7, _, _, _) => {
-                self.v_reg[byte2 as usize] =
-                    self.v_reg[byte2 as usize].wrapping_add((op & 0xFF) as u8)
-            }
-            (8, _, _, 0) => self.v_reg[byte2 as usize] = self.v_reg[byte3 as usize],
-            (8, _, _, 1) => self.v_reg[byte2 as usize] |= self.v_reg[byte3 as usize],
-            (8, _, _, 2) => self.v_reg[byte2 as usize] &= self.v_reg[byte3 as usize],
-            (8, _, _, 3) => self.v_reg[byte2 as usize] ^= self.v_reg[byte3 as usize],
-            (8, _, _, 4) => {
-                let carry;
-                (self.v_reg[byte2 as usize], carry) =
-                    self.v_reg[byte2 as usize].overflowing_add(self.v_reg[byte3 as usize]);
+    /// Installs the 4x5 glyphs `FX29` points at, 5 bytes per digit from 0 to
+    /// F, at `addr`. They are written into memory now and on every `reset`.
+    pub fn set_font(&mut self, addr: u16, glyphs: &[u8; FONTSET_SIZE]) -> Result<(), Chip8Error> {
+        let range = self.ram_range(addr, FONTSET_SIZE)?;
+        self.ram[range].copy_from_slice(glyphs);
+        self.font = *glyphs;
+        self.font_addr = addr;
 
-                self.v_reg[0xF] = if carry { 1 } else { 0 };
-            }
-            (8, _, _, 5) => {
-                let borrow;
-                (self.v_reg[byte2 as usize], borrow) =
-                    self.v_reg[byte2 as usize].overflowing_sub(self.v_reg[byte3 as usize]);
+        Ok(())
+    }
 
-                self.v_reg[0xF] = if borrow { 0 } else { 1 };
-            }
-            (8, _, _, 6) => {
-                self.v_reg[0xF] = self.v_reg[byte2 as usize] & 1;
-                self.v_reg[byte2 as usize] >>= 1;
-            }
-            (8, _, _, 7) => {
-                let borrow;
-                (self.v_reg[byte2 as usize], borrow) =
-                    self.v_reg[byte3 as usize].overflowing_sub(self.v_reg[byte2 as usize]);
+    /// Like `set_font` for the SUPER-CHIP 8x10 glyphs used by `FX30`.
+    pub fn set_big_font(
+        &mut self,
+        addr: u16,
+        glyphs: &[u8; BIG_FONTSET_SIZE],
+    ) -> Result<(), Chip8Error> {
+        let range = self.ram_range(addr, BIG_FONTSET_SIZE)?;
+        self.ram[range].copy_from_slice(glyphs);
+        self.big_font = *glyphs;
+        self.big_font_addr = addr;
 
-                self.v_reg[0xF] = if borrow { 1 } else { 0 };
-            }
-            (8, _, _, 0xE) => {
-                self.v_reg[0xF] = (self.v_reg[byte2 as usize] >> 7) & 1;
-                self.v_reg[byte2 as usize] <<= 1;
-            }
-            (9, _, _, 0) => {
-                if self.v_reg[byte2 as usize] != self.v_reg[byte3 as usize] {
-                    self.pc += 2
-                }
-            }
-            (0xA, _, _, _) => self.i_reg = op & 0xFFF,
-            (0xB, _, _, _) => self.pc = (self.v_reg[0] as u16) + (op & 0xFFF),
-            (0xC, _, _, _) => self.v_reg[byte2 as usize] = random::<u8>() & (op & 0xFF) as u8,
-            (0xD, _, _, _) => {
-                let x_start = self.v_reg[byte2 as usize] as u16;
-                let y_start = self.v_reg[byte3 as usize] as u16;
+        Ok(())
+    }
 
-                let mut flipped = false;
+    /// Address `FX29` loads into I for the low nibble of `digit`.
+    pub fn font_address(&self, digit: u8) -> u16 {
+        self.font_addr + (digit & 0x0F) as u16 * FONT_GLYPH_SIZE as u16
+    }
 
-                for j in 0..byte4 {
-                    let addr = self.i_reg + j as u16;
-                    let pixels = self.ram[addr as usize];
+    pub fn big_font_address(&self, digit: u8) -> u16 {
+        self.big_font_addr + (digit & 0x0F) as u16 * BIG_FONT_GLYPH_SIZE as u16
+    }
 
-                    for i in 0..8 {
-                        if (pixels & (0b1000_0000 >> i)) != 0 {
-                            let x = (x_start + i) as usize % SCREEN_WIDTH;
-                            let y = (y_start + j) as usize % SCREEN_HEIGHT;
+    /// Set once the program runs SUPER-CHIP's `00FD`. The machine then keeps
+    /// re-executing that instruction until `reset`.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
 
-                            let index = y * SCREEN_WIDTH + x;
-                            flipped |= self.screen[index];
-                            self.screen[index] ^= true;
-                        }
-                    }
-                }
+    /// True while `FX0A` is blocked, until the key it registers is released,
+    /// so a frontend can show a prompt.
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.key_wait != KeyWait::Idle
+    }
 
-                self.v_reg[0xF] = if flipped { 1 } else { 0 };
+    /// True while the sound timer is running and the tone should be audible.
+    pub fn is_beeping(&self) -> bool {
+        self.st > 0
+    }
+
+    /// Also fires the sound handler if this starts or stops the beep.
+    pub fn set_sound_timer(&mut self, value: u8) {
+        let was_beeping = self.is_beeping();
+        self.st = value;
+
+        let beeping = self.is_beeping();
+        if beeping != was_beeping {
+            if let Some(handler) = self.sound_handler.as_mut() {
+                handler(beeping);
             }
-            (0xE, _, 9, 0xE) => {
-                if self.keys[self.v_reg[byte2 as usize] as usize] {
-                    self.pc += 2
+            if let Some(sink) = self.audio_sink.as_mut() {
+                if beeping {
+                    sink.beep_start();
+                } else {
+                    sink.beep_stop();
                 }
             }
-            (0xE, _, 0xA, 1) => {
-                if !self.keys[self.v_reg[byte2 as usize] as usize] {
-                    self.pc += 2
+            self.emit(TraceEvent::Sound(beeping));
+        }
+    }
+
+    /// Fills `samples` with a square wave for as long as the sound timer will
+    /// keep running at `sample_rate`, and silence after that. Once an XO-CHIP
+    /// program has loaded a pattern with `F002`, its 128 bits are looped at
+    /// 4000 bits per second instead. Each call picks the waveform up where the
+    /// previous one left off, so buffers of any size join without clicks.
+    pub fn fill_audio(&mut self, samples: &mut [f32], sample_rate: u32) {
+        let audible = self.st as usize * sample_rate as usize / TIMER_HZ as usize;
+        let half_period = (sample_rate / (BEEP_HZ * 2)).max(1) as u64;
+        let pattern_bits = AUDIO_PATTERN_SIZE as u64 * 8;
+        let start = self.audio_phase;
+        self.audio_phase = start.wrapping_add(samples.len() as u64);
+
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let n = start.wrapping_add(i as u64);
+            let high = match self.audio_pattern {
+                Some(pattern) => {
+                    let bit =
+                        (n * AUDIO_PATTERN_HZ as u64 / sample_rate as u64 % pattern_bits) as usize;
+                    pattern[bit / 8] & (0x80 >> (bit % 8)) != 0
                 }
+                None => (n / half_period).is_multiple_of(2),
+            };
+            *sample = if i >= audible {
+                0.0
+            } else if high {
+                BEEP_AMPLITUDE
+            } else {
+                -BEEP_AMPLITUDE
+            };
+        }
+    }
+
+    /// Updates key `index` (0x0..=0xF); out-of-range indices are ignored.
+    pub fn keypress(&mut self, index: usize, pressed: bool) {
+        if let Some(key) = self.keys.get_mut(index) {
+            *key = pressed;
+        } else {
+            return;
+        }
+
+        self.key_wait = match self.key_wait {
+            KeyWait::Waiting if pressed => KeyWait::Pressed(index as u8),
+            KeyWait::Pressed(key) if !pressed && key as usize == index => KeyWait::Released(key),
+            other => other,
+        };
+    }
+
+    /// Currently held keys as a bitmask, bit `n` set when key `n` is down.
+    pub fn keys_held_mask(&self) -> u16 {
+        self.keys
+            .iter()
+            .enumerate()
+            .filter(|(_, pressed)| **pressed)
+            .fold(0, |mask, (i, _)| mask | (1 << i))
+    }
+
+    /// Presses and releases keys so that exactly those in `mask` are held.
+    /// Keys that change are applied lowest index first.
+    pub fn set_keys_held_mask(&mut self, mask: u16) {
+        let changed = mask ^ self.keys_held_mask();
+        for key in (0..NUM_KEYS).filter(|key| changed & (1 << key) != 0) {
+            self.keypress(key, mask & (1 << key) != 0);
+        }
+    }
+
+    /// True when every key in `mask` is held at once, e.g. a cheat chord.
+    pub fn all_keys_held(&self, mask: u16) -> bool {
+        self.keys_held_mask() & mask == mask
+    }
+
+    pub fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.step().map(|_| ())
+    }
+
+    /// Instructions per `run_frame`. The default of 10 (600 instructions per
+    /// second) suits most ROMs; older ones may want fewer, SUPER-CHIP games
+    /// often more.
+    pub fn set_speed(&mut self, instructions_per_frame: usize) {
+        self.cycles_per_frame = instructions_per_frame;
+    }
+
+    pub fn speed(&self) -> usize {
+        self.cycles_per_frame
+    }
+
+    /// Runs one 60 Hz frame: up to `speed()` instructions, or the VIP cycle
+    /// budget with the `vip_timing` quirk, followed by a single timer tick. The
+    /// frame ends early once the program blocks on `FX0A` or `00FD`, or after a
+    /// draw with the `display_wait` quirk.
+    pub fn run_frame(&mut self) -> Result<FrameOutcome, Chip8Error> {
+        self.record_frame();
+        self.apply_pokes();
+        let budget = self.frame_budget();
+        let mut cycles = 0;
+        let mut spent = 0;
+        while spent < budget {
+            let (cost, blocks) = self.frame_step()?;
+            cycles += 1;
+            spent += cost;
+            if blocks {
+                break;
             }
-            (0xF, _, 0, 7) => self.v_reg[byte2 as usize] = self.dt,
-            (0xF, _, 0, 0xA) => {
-                let mut pressed = false;
-                for i in 0..self.keys.len() {
-                    if self.keys[i] {
-                        self.v_reg[byte2 as usize] = i as u8;
-                        pressed = true;
-                        break;
-                    }
-                }
+        }
+        self.tick_timers();
 
-                if !pressed {
-                    self.pc -= 2;
-                }
+        Ok(FrameOutcome {
+            cycles,
+            waiting_for_key: self.is_waiting_for_key(),
+            halted: self.halted,
+        })
+    }
+
+    /// Ticks until the display differs from how it looked when the call
+    /// started, running at most `max_cycles` instructions. Breakpoints stop
+    /// the run the same way as in `run_until_breakpoint`.
+    pub fn run_until_draw_change(&mut self, max_cycles: usize) -> RunResult {
+        let initial = (self.hires, self.screen, self.second_plane);
+        for cycle in 1..=max_cycles {
+            if cycle > 1 && self.breakpoints.contains(&self.pc) {
+                return RunResult::Breakpoint(self.pc);
             }
-            (0xF, _, 1, 5) => self.dt = self.v_reg[byte2 as usize],
-            (0xF, _, 1, 8) => self.st = self.v_reg[byte2 as usize],
-            (0xF, _, 1, 0xE) => {
-                self.i_reg = self.i_reg.wrapping_add(self.v_reg[byte2 as usize] as u16)
+            if let Err(err) = self.tick() {
+                return RunResult::Error(err);
             }
-            (0xF, _, 2, 9) => self.i_reg = (self.v_reg[byte2 as usize] * 5) as u16,
-            (0xF, _, 3, 3) => {
-                let mut vx = self.v_reg[byte2 as usize];
-                for i in 0..3 {
-                    let tmp = vx % 10;
-                    self.ram[(self.i_reg + (2 - i)) as usize] = tmp;
-                    vx /= 10;
-                }
+            if (self.hires, self.screen, self.second_plane) != initial {
+                return RunResult::DisplayChanged { cycles: cycle };
             }
-            (0xF, _, 5, 5) => {
-                for index in 0..=byte2 {
-                    self.ram[(self.i_reg + index) as usize] = self.v_reg[index as usize];
+        }
+
+        RunResult::CycleLimit
+    }
+
+    // A font placed past 4 KiB is lost if XO-CHIP is switched off again
+    fn load_fonts(&mut self) {
+        let font = self.font_addr as usize;
+        if let Some(dst) = self.ram.get_mut(font..font + FONTSET_SIZE) {
+            dst.copy_from_slice(&self.font);
+        }
+        let big_font = self.big_font_addr as usize;
+        if let Some(dst) = self.ram.get_mut(big_font..big_font + BIG_FONTSET_SIZE) {
+            dst.copy_from_slice(&self.big_font);
+        }
+    }
+
+    fn display_changed(&mut self) {
+        self.screen_dirty = true;
+        self.display_generation = self.display_generation.wrapping_add(1);
+    }
+
+    fn selected_planes(&mut self) -> impl Iterator<Item = &mut [bool; SCREEN_BUFFER_SIZE]> {
+        let mask = self.plane_mask;
+        [&mut self.screen, &mut self.second_plane]
+            .into_iter()
+            .enumerate()
+            .filter(move |(i, _)| mask & (1 << i) != 0)
+            .map(|(_, plane)| plane)
+    }
+
+    fn clear_screen(&mut self) {
+        for plane in self.selected_planes() {
+            plane.fill(false);
+        }
+        self.display_changed();
+    }
+
+    fn scroll_down(&mut self, lines: usize) {
+        let (width, height) = self.display_dimensions();
+        self.display_changed();
+        for plane in self.selected_planes() {
+            for y in (0..height).rev() {
+                for x in 0..width {
+                    plane[y * width + x] = y >= lines && plane[(y - lines) * width + x];
+                }
+            }
+        }
+    }
+
+    fn scroll_right(&mut self) {
+        let (width, height) = self.display_dimensions();
+        self.display_changed();
+        for plane in self.selected_planes() {
+            for y in 0..height {
+                let row = &mut plane[y * width..(y + 1) * width];
+                row.copy_within(..width - SCROLL_STEP, SCROLL_STEP);
+                row[..SCROLL_STEP].fill(false);
+            }
+        }
+    }
+
+    fn scroll_left(&mut self) {
+        let (width, height) = self.display_dimensions();
+        self.display_changed();
+        for plane in self.selected_planes() {
+            for y in 0..height {
+                let row = &mut plane[y * width..(y + 1) * width];
+                row.copy_within(SCROLL_STEP.., 0);
+                row[width - SCROLL_STEP..].fill(false);
+            }
+        }
+    }
+
+    fn ram_range(&self, start: u16, len: usize) -> Result<Range<usize>, Chip8Error> {
+        let start = start as usize;
+        let end = start + len;
+        if end > self.ram.len() {
+            return Err(Chip8Error::MemoryOutOfBounds {
+                addr: start.max(self.ram.len()),
+            });
+        }
+
+        Ok(start..end)
+    }
+
+    fn record_read(&mut self, range: &Range<usize>) {
+        self.emit(TraceEvent::MemoryRead {
+            addr: range.start as u16,
+            len: range.len(),
+        });
+    }
+
+    // Also remembers the first watched address an instruction writes to
+    fn record_write(&mut self, range: &Range<usize>) {
+        self.emit(TraceEvent::MemoryWrite {
+            addr: range.start as u16,
+            len: range.len(),
+        });
+        if self.watch_hit.is_some() {
+            return;
+        }
+        self.watch_hit = self.watchpoints.iter().find_map(|watch| {
+            let start = range.start.max(watch.start as usize);
+            (start < range.end.min(watch.end as usize)).then_some(start as u16)
+        });
+    }
+
+    fn fetch(&mut self) -> Result<u16, Chip8Error> {
+        let op = match self.replaced_opcode(self.pc) {
+            Some(op) => op,
+            None => {
+                let span = self
+                    .mem_span(self.pc, 2)
+                    .map_err(|_| Chip8Error::PcOutOfBounds { pc: self.pc })?;
+                let high = self.bus_read(self.mem_index(span.start)) as u16;
+                let low = self.bus_read(self.mem_index(span.start + 1)) as u16;
+                (high << 8) | low
+            }
+        };
+        self.pc = self.pc.wrapping_add(2);
+
+        Ok(op)
+    }
+
+    // Points the PC back at the instruction just fetched, which may have
+    // wrapped past the end of memory
+    fn repeat_instruction(&mut self) {
+        self.pc = (self.pc.wrapping_sub(2) as usize % self.ram.len()) as u16;
+    }
+
+    // XO-CHIP skips jump over both words of an `F000 NNNN` long load
+    fn skip(&mut self) {
+        let long = self.quirks.xo_chip && self.read16(self.pc) == Ok(0xF000);
+        self.pc = self.pc.wrapping_add(if long { 4 } else { 2 });
+    }
+
+    fn execute(&mut self, op: u16) -> Result<(), Chip8Error> {
+        match Opcode::decode(op) {
+            Opcode::ClearScreen => self.clear_screen(),
+            Opcode::Return => self.pc = self.pop()?,
+            Opcode::ScrollDown(n) => self.scroll_down(n as usize),
+            Opcode::ScrollRight => self.scroll_right(),
+            Opcode::ScrollLeft => self.scroll_left(),
+            Opcode::LowRes => self.set_hires(false),
+            Opcode::HighRes => self.set_hires(true),
+            Opcode::Exit => {
+                self.halted = true;
+                self.repeat_instruction();
+            }
+            Opcode::SelectPlanes(mask) if self.quirks.xo_chip => self.plane_mask = mask & 0x3,
+            Opcode::Jump(addr) => self.pc = addr,
+            Opcode::Call(addr) => {
+                self.push(self.pc)?;
+                self.pc = addr
+            }
+            Opcode::SkipEqImm { x, nn } => {
+                if self.v_reg[x] == nn {
+                    self.skip()
+                }
+            }
+            Opcode::SkipNeImm { x, nn } => {
+                if self.v_reg[x] != nn {
+                    self.skip()
+                }
+            }
+            Opcode::SkipEqReg { x, y } => {
+                if self.v_reg[x] == self.v_reg[y] {
+                    self.skip()
+                }
+            }
+            Opcode::LoadImm { x, nn } => self.v_reg[x] = nn,
+            Opcode::AddImm { x, nn } => self.v_reg[x] = self.v_reg[x].wrapping_add(nn),
+            Opcode::LoadReg { x, y } => self.v_reg[x] = self.v_reg[y],
+            Opcode::Or { x, y } => self.v_reg[x] |= self.v_reg[y],
+            Opcode::And { x, y } => self.v_reg[x] &= self.v_reg[y],
+            Opcode::Xor { x, y } => self.v_reg[x] ^= self.v_reg[y],
+            Opcode::AddReg { x, y } => {
+                let carry;
+                (self.v_reg[x], carry) = self.v_reg[x].overflowing_add(self.v_reg[y]);
+
+                self.v_reg[0xF] = if carry { 1 } else { 0 };
+            }
+            Opcode::SubReg { x, y } => {
+                let borrow;
+                (self.v_reg[x], borrow) = self.v_reg[x].overflowing_sub(self.v_reg[y]);
+
+                self.v_reg[0xF] = if borrow { 0 } else { 1 };
+            }
+            Opcode::ShiftRight { x, y } => {
+                if self.quirks.shift_uses_vy {
+                    self.v_reg[x] = self.v_reg[y];
                 }
+                self.v_reg[0xF] = self.v_reg[x] & 1;
+                self.v_reg[x] >>= 1;
             }
-            (0xF, _, 6, 5) => {
-                for index in 0..=byte2 {
-                    self.v_reg[index as usize] = self.ram[(self.i_reg + index) as usize];
+            Opcode::SubNReg { x, y } => {
+                let borrow;
+                (self.v_reg[x], borrow) = self.v_reg[y].overflowing_sub(self.v_reg[x]);
+
+                self.v_reg[0xF] = if borrow { 1 } else { 0 };
+            }
+            Opcode::ShiftLeft { x, y } => {
+                if self.quirks.shift_uses_vy {
+                    self.v_reg[x] = self.v_reg[y];
+                }
+                self.v_reg[0xF] = (self.v_reg[x] >> 7) & 1;
+                self.v_reg[x] <<= 1;
+            }
+            Opcode::SkipNeReg { x, y } => {
+                if self.v_reg[x] != self.v_reg[y] {
+                    self.skip()
+                }
+            }
+            Opcode::LoadIndex(addr) => self.i_reg = addr,
+            Opcode::LoadLongIndex if self.quirks.xo_chip => self.i_reg = self.fetch()?,
+            Opcode::JumpOffset { x, addr } => {
+                let offset = if self.quirks.jump_offset_uses_vx {
+                    self.v_reg[x]
+                } else {
+                    self.v_reg[0]
+                };
+                self.pc = (offset as u16) + addr
+            }
+            Opcode::Random { x, nn } => self.v_reg[x] = self.rng.gen::<u8>() & nn,
+            Opcode::Draw { x, y, n } => {
+                // Coordinates are latched before drawing and VF is only written
+                // once every row is done, so `DRW VF, ..` and `DRW .., VF` still
+                // draw at the original position.
+                let (width, height) = self.display_dimensions();
+                let x_start = self.v_reg[x] as usize % width;
+                let y_start = self.v_reg[y] as usize % height;
+                let clip = self.quirks.clip_sprites;
+
+                // DXY0 draws a 16x16 sprite, two bytes per row, in hires mode
+                let (rows, cols) = if n == 0 && self.hires {
+                    (16, 16)
+                } else {
+                    (n as usize, 8)
+                };
+                let bytes_per_row = cols / 8;
+
+                // With both XO-CHIP planes selected, the second plane's sprite
+                // data follows the first's
+                let mask = self.plane_mask;
+                let sprite_len = rows * bytes_per_row;
+                let sprite = self.mem_span(self.i_reg, sprite_len * mask.count_ones() as usize)?;
+                self.record_read(&sprite);
+                let mut data = [0; MAX_SPRITE_BYTES];
+                for (byte, addr) in data.iter_mut().zip(sprite) {
+                    *byte = self.bus_read(self.mem_index(addr));
+                }
+                let mut data_start = 0;
+                let mut flipped = false;
+
+                let planes = [&mut self.screen, &mut self.second_plane];
+                for (plane, buffer) in planes.into_iter().enumerate() {
+                    if mask & (1 << plane) == 0 {
+                        continue;
+                    }
+
+                    for j in 0..rows {
+                        let row_start = data_start + j * bytes_per_row;
+                        let pixels = (row_start..row_start + bytes_per_row)
+                            .fold(0u16, |acc, addr| (acc << 8) | data[addr] as u16)
+                            << (16 - cols);
+
+                        for i in 0..cols {
+                            if (pixels & (0x8000 >> i)) != 0 {
+                                if clip && (x_start + i >= width || y_start + j >= height) {
+                                    continue;
+                                }
+                                let x = (x_start + i) % width;
+                                let y = (y_start + j) % height;
+
+                                let index = y * width + x;
+                                flipped |= buffer[index];
+                                buffer[index] ^= true;
+                            }
+                        }
+                    }
+                    data_start += sprite_len;
                 }
+
+                self.v_reg[0xF] = if flipped { 1 } else { 0 };
+                self.display_changed();
+                self.emit(TraceEvent::Draw {
+                    x: x_start as u8,
+                    y: y_start as u8,
+                    rows,
+                    collision: flipped,
+                });
+            }
+            // Only the low nibble of VX names a key, as on the COSMAC VIP
+            Opcode::SkipKeyPressed { x } => {
+                if self.keys[(self.v_reg[x] & 0x0F) as usize] {
+                    self.skip()
+                }
+            }
+            Opcode::SkipKeyNotPressed { x } => {
+                if !self.keys[(self.v_reg[x] & 0x0F) as usize] {
+                    self.skip()
+                }
+            }
+            Opcode::LoadDelay { x } => self.v_reg[x] = self.dt,
+            Opcode::WaitKey { x } => {
+                // Completes only once a pressed key is released again. If several
+                // keys are already held when the wait starts the lowest index
+                // wins; replays depend on this choice, so keep it stable.
+                self.key_wait = match self.key_wait {
+                    KeyWait::Idle => {
+                        self.emit(TraceEvent::KeyWaitStarted);
+                        match self.keys.iter().position(|pressed| *pressed) {
+                            Some(key) => KeyWait::Pressed(key as u8),
+                            None => KeyWait::Waiting,
+                        }
+                    }
+                    KeyWait::Released(key) => {
+                        self.v_reg[x] = key;
+                        self.emit(TraceEvent::KeyWaitFinished { key });
+                        KeyWait::Idle
+                    }
+                    waiting => waiting,
+                };
+
+                if self.key_wait != KeyWait::Idle {
+                    self.repeat_instruction();
+                }
+            }
+            Opcode::SetDelay { x } => self.dt = self.v_reg[x],
+            Opcode::SetSound { x } => self.set_sound_timer(self.v_reg[x]),
+            Opcode::AddIndex { x } => {
+                self.i_reg = self.i_reg.wrapping_add(self.v_reg[x] as u16);
+                if self.quirks.add_index_sets_vf {
+                    self.v_reg[0xF] = (self.i_reg > 0xFFF) as u8;
+                }
+            }
+            Opcode::LoadFont { x } => self.i_reg = self.font_address(self.v_reg[x]),
+            Opcode::LoadBigFont { x } => self.i_reg = self.big_font_address(self.v_reg[x]),
+            Opcode::StoreBcd { x } => {
+                let digits = self.mem_span(self.i_reg, 3)?;
+                self.record_write(&digits);
+                let mut vx = self.v_reg[x];
+                for i in 0..3 {
+                    let tmp = vx % 10;
+                    let index = self.mem_index(digits.start + (2 - i));
+                    self.bus_write(index, tmp);
+                    vx /= 10;
+                }
+            }
+            Opcode::StoreRegs { x } => {
+                let range = self.mem_span(self.i_reg, x + 1)?;
+                self.record_write(&range);
+                for (i, addr) in range.enumerate() {
+                    let index = self.mem_index(addr);
+                    self.bus_write(index, self.v_reg[i]);
+                }
+                if self.quirks.load_store_increments_i {
+                    self.i_reg = self.i_reg.wrapping_add(x as u16 + 1);
+                }
+            }
+            Opcode::LoadRegs { x } => {
+                let range = self.mem_span(self.i_reg, x + 1)?;
+                self.record_read(&range);
+                for (i, addr) in range.enumerate() {
+                    let index = self.mem_index(addr);
+                    self.v_reg[i] = self.bus_read(index);
+                }
+                if self.quirks.load_store_increments_i {
+                    self.i_reg = self.i_reg.wrapping_add(x as u16 + 1);
+                }
+            }
+            Opcode::StoreFlags { x } => {
+                let count = x.min(NUM_RPL_FLAGS - 1) + 1;
+                self.rpl_flags[..count].copy_from_slice(&self.v_reg[..count]);
+                if let Some(hook) = self.rpl_flags_hook.as_mut() {
+                    hook(&self.rpl_flags);
+                }
+                if let Some(storage) = self.storage.as_mut() {
+                    storage.save_flags(&self.rpl_flags);
+                }
+            }
+            Opcode::LoadFlags { x } => {
+                let count = x.min(NUM_RPL_FLAGS - 1) + 1;
+                self.v_reg[..count].copy_from_slice(&self.rpl_flags[..count]);
+            }
+            Opcode::LoadAudioPattern if self.quirks.xo_chip => {
+                let range = self.mem_span(self.i_reg, AUDIO_PATTERN_SIZE)?;
+                self.record_read(&range);
+                let mut pattern = [0; AUDIO_PATTERN_SIZE];
+                for (byte, addr) in pattern.iter_mut().zip(range) {
+                    *byte = self.bus_read(self.mem_index(addr));
+                }
+                self.audio_pattern = Some(pattern);
+                if let Some(sink) = self.audio_sink.as_mut() {
+                    sink.pattern_loaded(&pattern);
+                }
+            }
+            Opcode::SelectPlanes(_)
+            | Opcode::LoadLongIndex
+            | Opcode::LoadAudioPattern
+            | Opcode::Unknown(_) => match self.quirks.invalid_opcodes {
+                InvalidOpcodePolicy::Ignore => {}
+                InvalidOpcodePolicy::Log => self.emit(TraceEvent::InvalidOpcode {
+                    pc: self.pc.wrapping_sub(2),
+                    opcode: op,
+                }),
+                InvalidOpcodePolicy::Error => return Err(Chip8Error::InvalidOpcode(op)),
+            },
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(words: &[u16]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_be_bytes()).collect()
+    }
+
+    fn machine_with(quirks: Quirks, words: &[u16]) -> Machine {
+        let mut machine = Machine::new_with_quirks(quirks);
+        machine.load(&program(words)).unwrap();
+        machine
+    }
+
+    fn run(words: &[u16]) -> Machine {
+        let mut machine = machine_with(Quirks::default(), words);
+        for _ in words {
+            machine.tick().unwrap();
+        }
+        machine
+    }
+
+    fn pixel(machine: &Machine, x: usize, y: usize) -> bool {
+        let (width, _) = machine.display_dimensions();
+        machine.get_display()[y * width + x]
+    }
+
+    // The top row of the "0" glyph is 0xF0: four pixels from `x`
+    fn assert_glyph_row(machine: &Machine, x: usize, y: usize, lit: bool) {
+        for i in 0..4 {
+            assert_eq!(pixel(machine, x + i, y), lit, "pixel ({}, {})", x + i, y);
+        }
+        assert!(!pixel(machine, x + 4, y));
+    }
+
+    #[test]
+    fn draw_with_vf_as_x_uses_the_original_position() {
+        let mut machine = run(&[0x6F0A, 0x6005, 0xA000, 0xDF01]);
+        assert_glyph_row(&machine, 10, 5, true);
+        assert_eq!(machine.v(0xF), 0);
+
+        // VF was overwritten with 0, so point it back at the sprite to collide
+        machine.load_chunk(8, &program(&[0x6F0A, 0xDF01])).unwrap();
+        machine.tick().unwrap();
+        machine.tick().unwrap();
+        assert_glyph_row(&machine, 10, 5, false);
+        assert_eq!(machine.v(0xF), 1);
+    }
+
+    #[test]
+    fn draw_with_vf_as_y_uses_the_original_position() {
+        let mut machine = run(&[0x600A, 0x6F05, 0xA000, 0xD0F1]);
+        assert_glyph_row(&machine, 10, 5, true);
+        assert_eq!(machine.v(0xF), 0);
+
+        machine.load_chunk(8, &program(&[0x6F05, 0xD0F1])).unwrap();
+        machine.tick().unwrap();
+        machine.tick().unwrap();
+        assert_glyph_row(&machine, 10, 5, false);
+        assert_eq!(machine.v(0xF), 1);
+    }
+
+    #[test]
+    fn return_with_an_empty_stack_underflows() {
+        let mut machine = machine_with(Quirks::default(), &[0x00EE]);
+        assert_eq!(machine.tick(), Err(Chip8Error::StackUnderflow));
+    }
+
+    #[test]
+    fn register_load_past_the_end_of_memory_fails() {
+        let mut machine = machine_with(Quirks::default(), &[0xFF65]);
+        machine.set_index_register(0xFFE);
+        assert_eq!(
+            machine.tick(),
+            Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000 })
+        );
+    }
+
+    #[test]
+    fn register_load_and_store_wrap_with_wrap_memory() {
+        let quirks = Quirks {
+            wrap_memory: true,
+            ..Quirks::default()
+        };
+        let mut machine = machine_with(quirks, &[0xF265, 0x6011, 0x6122, 0xF155]);
+        machine.write8(0xFFE, 0xAA).unwrap();
+        machine.write8(0xFFF, 0xBB).unwrap();
+        machine.set_index_register(0xFFE);
+        machine.tick().unwrap();
+        assert_eq!(machine.registers()[..3], [0xAA, 0xBB, FONTSET[0]]);
+
+        machine.set_index_register(0xFFF);
+        for _ in 0..3 {
+            machine.tick().unwrap();
+        }
+        assert_eq!(machine.ram()[0xFFF], 0x11);
+        assert_eq!(machine.ram()[0], 0x22);
+    }
+
+    #[test]
+    fn key_skips_use_the_low_nibble_of_vx() {
+        let mut machine = machine_with(Quirks::default(), &[0x6025, 0xE09E]);
+        machine.keypress(5, true);
+        machine.tick().unwrap();
+        machine.tick().unwrap();
+        assert_eq!(machine.program_counter(), START_ADDR + 6);
+    }
+
+    #[test]
+    fn audio_is_silent_without_the_sound_timer() {
+        let mut machine = Machine::new();
+        let mut samples = [1.0; 735];
+        machine.fill_audio(&mut samples, 44100);
+        assert!(samples.iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn audio_lasts_as_long_as_the_sound_timer() {
+        let mut machine = Machine::new();
+        machine.set_sound_timer(6);
+        let mut samples = [0.0; 5000];
+        machine.fill_audio(&mut samples, 44100);
+        // 6 ticks at 60 Hz is a tenth of a second
+        assert!(samples[..4410].iter().all(|sample| *sample != 0.0));
+        assert!(samples[4410..].iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn audio_continues_across_buffers() {
+        let mut whole = Machine::new();
+        whole.set_sound_timer(60);
+        let mut expected = [0.0; 1470];
+        whole.fill_audio(&mut expected, 44100);
+
+        let mut split = Machine::new();
+        split.set_sound_timer(60);
+        let mut samples = [0.0; 1470];
+        let (first, second) = samples.split_at_mut(735);
+        split.fill_audio(first, 44100);
+        split.fill_audio(second, 44100);
+        assert_eq!(samples, expected);
+    }
+
+    #[test]
+    fn restored_machines_draw_the_same_random_numbers() {
+        let mut machine = machine_with(Quirks::default(), &[0xC0FF, 0x1200]);
+        machine.tick().unwrap();
+        let state = machine.save_state();
+        machine.tick().unwrap();
+        machine.tick().unwrap();
+        let expected = machine.v(0);
+
+        let mut restored = Machine::new();
+        restored.load_state(&state).unwrap();
+        restored.tick().unwrap();
+        restored.tick().unwrap();
+        assert_eq!(restored.v(0), expected);
+        assert_eq!(restored.save_state(), machine.save_state());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn machine_state_round_trips_through_serde() {
+        let mut machine = machine_with(Quirks::default(), &[0xC0FF, 0xA000, 0xD015, 0x1200]);
+        machine.keypress(3, true);
+        machine.set_sound_timer(9);
+        for _ in 0..3 {
+            machine.tick().unwrap();
+        }
+        let state = machine.save_state();
+
+        let json = serde_json::to_string(&state).unwrap();
+        let decoded: MachineState = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, state);
+    }
+
+    #[test]
+    fn machine_state_round_trips_through_bytes() {
+        let quirks = CompatibilityProfile::XoChip.into();
+        let mut machine = machine_with(quirks, &[0x2206, 0x0000, 0x0000, 0xC0FF, 0xA000, 0xD015]);
+        machine.keypress(3, true);
+        machine.set_sound_timer(9);
+        for _ in 0..4 {
+            machine.tick().unwrap();
+        }
+        let state = machine.save_state();
+
+        let bytes = state.to_bytes();
+        assert_eq!(bytes.len(), MachineState::encoded_len(XO_RAM_SIZE, 1));
+        let mut padded = bytes.clone();
+        padded.resize(bytes.len() + 30, 0);
+        assert_eq!(MachineState::from_bytes(&padded), Some(state));
+        assert_eq!(MachineState::from_bytes(&bytes[..bytes.len() - 1]), None);
+    }
+
+    #[test]
+    fn scroll_down_moves_a_hires_sprite() {
+        let mut sprite = [0; 32];
+        sprite[0] = 0x80;
+        sprite[31] = 0x01;
+        let mut rom = program(&[0x00FF, 0x6000, 0xA20A, 0xD000, 0x00C2]);
+        rom.extend_from_slice(&sprite);
+        let mut machine = Machine::new();
+        machine.load(&rom).unwrap();
+        for _ in 0..5 {
+            machine.tick().unwrap();
+        }
+
+        assert!(machine.is_hires());
+        assert!(!pixel(&machine, 0, 0));
+        assert!(pixel(&machine, 0, 2));
+        assert!(!pixel(&machine, 15, 15));
+        assert!(pixel(&machine, 15, 17));
+    }
+
+    #[test]
+    fn shift_uses_vy_copies_vy_before_shifting() {
+        let words = [0x60FF, 0x6104, 0x8016];
+        let mut machine = run(&words);
+        assert_eq!((machine.v(0), machine.v(0xF)), (0x7F, 1));
+
+        let quirks = Quirks {
+            shift_uses_vy: true,
+            ..Quirks::default()
+        };
+        machine = machine_with(quirks, &words);
+        for _ in words {
+            machine.tick().unwrap();
+        }
+        assert_eq!((machine.v(0), machine.v(0xF)), (0x02, 0));
+    }
+
+    #[test]
+    fn wait_key_completes_on_release() {
+        let mut machine = machine_with(Quirks::default(), &[0xF00A]);
+        machine.tick().unwrap();
+        assert!(machine.is_waiting_for_key());
+
+        machine.keypress(4, true);
+        machine.tick().unwrap();
+        assert_eq!(machine.program_counter(), START_ADDR);
+
+        machine.keypress(4, false);
+        machine.tick().unwrap();
+        assert_eq!(machine.program_counter(), START_ADDR + 2);
+        assert_eq!(machine.v(0), 4);
+        assert!(!machine.is_waiting_for_key());
+    }
+
+    #[test]
+    fn wait_key_with_several_keys_held_stores_the_lowest() {
+        let mut machine = machine_with(Quirks::default(), &[0xF00A]);
+        machine.keypress(7, true);
+        machine.keypress(3, true);
+        machine.tick().unwrap();
+
+        // Releasing the other key doesn't finish the wait
+        machine.keypress(7, false);
+        machine.tick().unwrap();
+        assert!(machine.is_waiting_for_key());
+
+        machine.keypress(3, false);
+        machine.tick().unwrap();
+        assert_eq!(machine.v(0), 3);
+        assert!(!machine.is_waiting_for_key());
+    }
+
+    #[test]
+    fn load_chunk_accepts_exactly_the_available_space() {
+        let max = RAM_SIZE - START_ADDR as usize;
+        let mut machine = Machine::new();
+        assert_eq!(machine.load(&vec![0xAB; max]), Ok(()));
+        assert_eq!(machine.ram()[RAM_SIZE - 1], 0xAB);
+    }
+
+    #[test]
+    fn load_chunk_rejects_one_byte_too_many() {
+        let max = RAM_SIZE - START_ADDR as usize;
+        let mut machine = Machine::new();
+        assert_eq!(
+            machine.load(&vec![0; max + 1]),
+            Err(Chip8Error::RomTooLarge { size: max + 1, max })
+        );
+        assert_eq!(
+            machine.load_chunk(10, &vec![0; max - 9]),
+            Err(Chip8Error::RomTooLarge { size: max + 1, max })
+        );
+    }
+
+    #[test]
+    fn run_frame_ticks_the_delay_timer_once() {
+        let mut machine = machine_with(Quirks::default(), &[0x600A, 0xF015, 0x1204]);
+        machine.run_frame().unwrap();
+        assert_eq!(machine.delay_timer(), 9);
+        machine.run_frame().unwrap();
+        assert_eq!(machine.delay_timer(), 8);
+    }
+
+    #[test]
+    fn trace_hook_sees_every_instruction() {
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+
+        let trace = Rc::new(RefCell::new(Vec::new()));
+        let mut machine = machine_with(Quirks::default(), &[0x6001, 0x6102, 0x8014]);
+        let sink = Rc::clone(&trace);
+        machine.set_trace_hook(Box::new(move |pc, opcode| {
+            sink.borrow_mut().push((pc, opcode))
+        }));
+        for _ in 0..3 {
+            machine.tick().unwrap();
+        }
+        assert_eq!(
+            *trace.borrow(),
+            [(0x200, 0x6001), (0x202, 0x6102), (0x204, 0x8014)]
+        );
+    }
+
+    #[test]
+    fn drawing_into_the_second_plane_leaves_the_first_alone() {
+        let quirks = CompatibilityProfile::XoChip.into();
+        let mut machine = machine_with(quirks, &[0xF201, 0xA000, 0xD015]);
+        for _ in 0..3 {
+            machine.tick().unwrap();
+        }
+        let (first, second) = machine.get_display_planes();
+        assert!(first.iter().all(|pixel| !pixel));
+        assert_eq!(second[..5], [true, true, true, true, false]);
+    }
+
+    #[test]
+    fn plane_selection_ignores_the_upper_bits() {
+        let quirks = Quirks {
+            wrap_memory: false,
+            ..CompatibilityProfile::XoChip.into()
+        };
+        // Both planes read two bytes from the last two addresses in memory
+        let mut machine = machine_with(quirks, &[0xF000, 0xFFFE, 0xFF01, 0xD011]);
+        for _ in 0..3 {
+            machine.tick().unwrap();
+        }
+        assert_eq!(machine.registers()[0xF], 0);
+    }
+
+    #[test]
+    fn sprites_wrap_unless_clipped() {
+        let words = [0x603C, 0x6100, 0xA208, 0xD011, 0xFF00];
+        let quirks = Quirks {
+            clip_sprites: true,
+            ..Quirks::default()
+        };
+        let mut machine = machine_with(quirks, &words);
+        for _ in 0..4 {
+            machine.tick().unwrap();
+        }
+        assert!((60..64).all(|x| pixel(&machine, x, 0)));
+        assert!((0..4).all(|x| !pixel(&machine, x, 0)));
+
+        let mut machine = machine_with(Quirks::default(), &words);
+        for _ in 0..4 {
+            machine.tick().unwrap();
+        }
+        assert!((0..4).all(|x| pixel(&machine, x, 0)));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn load_from_path_reads_the_file() {
+        let dir = std::env::temp_dir();
+        let rom_path = dir.join(format!("chip8_core_test_{}.ch8", std::process::id()));
+        let empty_path = dir.join(format!("chip8_core_test_{}_empty.ch8", std::process::id()));
+        std::fs::write(&rom_path, program(&[0x6A02])).unwrap();
+        std::fs::write(&empty_path, []).unwrap();
+
+        let mut machine = Machine::new();
+        let loaded = machine.load_from_path(&rom_path);
+        let empty = Machine::new().load_from_path(&empty_path);
+        std::fs::remove_file(&rom_path).unwrap();
+        std::fs::remove_file(&empty_path).unwrap();
+
+        assert_eq!(loaded, Ok(()));
+        assert_eq!(machine.ram()[0x200..0x202], [0x6A, 0x02]);
+        assert_eq!(empty, Ok(()));
+        assert_eq!(
+            Machine::new().load_from_path(&rom_path),
+            Err(Chip8Error::Io(std::io::ErrorKind::NotFound))
+        );
+    }
+
+    #[test]
+    fn font_lookup_uses_the_low_nibble() {
+        let machine = run(&[0x60FF, 0xF029]);
+        assert_eq!(machine.index_register(), 75);
+    }
+
+    #[test]
+    fn rpl_flags_restore_the_saved_registers() {
+        let machine = run(&[
+            0x6001, 0x6102, 0x6203, 0x6304, 0xF375, 0x6000, 0x6100, 0x6200, 0x6300, 0xF385,
+        ]);
+        assert_eq!(machine.registers()[..4], [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn run_until_breakpoint_resumes_past_the_breakpoint() {
+        let mut machine = machine_with(Quirks::default(), &[0x7001, 0x7101, 0x1200]);
+        machine.add_breakpoint(0x202);
+        assert_eq!(
+            machine.run_until_breakpoint(100),
+            StopReason::Breakpoint(0x202)
+        );
+        assert_eq!(
+            machine.run_until_breakpoint(100),
+            StopReason::Breakpoint(0x202)
+        );
+        assert_eq!(machine.registers()[0], 2);
+    }
+
+    #[test]
+    fn sound_handler_fires_on_both_edges() {
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+
+        let edges = Rc::new(RefCell::new(Vec::new()));
+        let mut machine = machine_with(Quirks::default(), &[0x6002, 0xF018]);
+        let sink = Rc::clone(&edges);
+        machine.set_sound_handler(Box::new(move |on| sink.borrow_mut().push(on)));
+        machine.tick().unwrap();
+        machine.tick().unwrap();
+        assert_eq!(*edges.borrow(), [true]);
+        machine.tick_timers();
+        machine.tick_timers();
+        assert_eq!(*edges.borrow(), [true, false]);
+    }
+
+    #[test]
+    fn disassemble_names_each_instruction() {
+        let words = [
+            0x00E0, 0x6A02, 0xD01F, 0x5121, 0xF007, 0xF00A, 0xF015, 0xF018, 0xF01E, 0xF029, 0xF033,
+            0xF055, 0xF065,
+        ];
+        let lines = disassemble(&program(&words));
+        let expected = [
+            "CLS",
+            "LD VA, 0x02",
+            "DRW V0, V1, 15",
+            "DW 0x5121",
+            "LD V0, DT",
+            "LD V0, K",
+            "LD DT, V0",
+            "LD ST, V0",
+            "ADD I, V0",
+            "LD F, V0",
+            "LD B, V0",
+            "LD [I], V0",
+            "LD V0, [I]",
+        ];
+        assert_eq!(lines.len(), expected.len());
+        for (i, ((addr, text), want)) in lines.iter().zip(expected).enumerate() {
+            assert_eq!(*addr, START_ADDR + 2 * i as u16);
+            assert_eq!(text, want);
+        }
+    }
+
+    #[test]
+    fn disassemble_shows_a_trailing_byte() {
+        assert_eq!(disassemble(&[0xFF]), [(0x200, String::from("DB 0xFF"))]);
+    }
+
+    #[test]
+    fn seeded_machines_draw_the_same_random_numbers() {
+        let mut machine = Machine::with_seed(42);
+        machine.load(&program(&[0xC0FF])).unwrap();
+        machine.tick().unwrap();
+        assert_eq!(machine.registers()[0], 162);
+    }
+
+    #[test]
+    fn only_drawing_marks_the_display_dirty() {
+        let mut machine = machine_with(Quirks::default(), &[0x7001, 0xD015]);
+        machine.clear_display_dirty();
+        machine.tick().unwrap();
+        assert!(!machine.is_display_dirty());
+        machine.tick().unwrap();
+        assert!(machine.is_display_dirty());
+    }
+
+    #[test]
+    fn golden_runs_reproduce() {
+        let rom = asm::assemble(
+            "    LD V0, 0
+                 LD V1, 0
+             loop:
+                 LD F, V0
+                 DRW V1, V2, 5
+                 ADD V1, 5
+                 ADD V0, 1
+                 SE V0, 4
+                 JP loop
+             end:
+                 JP end",
+        )
+        .unwrap();
+        let fresh = || {
+            let mut machine = Machine::with_seed(1);
+            machine.load(&rom).unwrap();
+            machine
+        };
+
+        // Digits 0 to 3 across the top-left corner, recorded once and checked
+        // in so that a change to any opcode path shows up here
+        let mut display = vec![0u128; 32];
+        display[..5].copy_from_slice(&[
+            0xF13D_E000_0000_0000,
+            0x9304_2000_0000_0000,
+            0x913D_E000_0000_0000,
+            0x9120_2000_0000_0000,
+            0xF3BD_E000_0000_0000,
+        ]);
+        let golden = harness::Golden {
+            frames: 3,
+            state_hash: 0xB1CB_2EF7_4008_E1E0,
+            display,
+        };
+        assert_eq!(fresh().record_golden(3).unwrap(), golden);
+        assert_eq!(fresh().check_golden(&golden), Ok(()));
+
+        let mut shifted = fresh();
+        shifted.set_v(2, 1);
+        match shifted.check_golden(&golden) {
+            Err(harness::HarnessError::Mismatch(mismatch)) => assert!(!mismatch.rows.is_empty()),
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn mode_switch_keeps_pixels_without_mode_switch_clears() {
+        let words = [0xA000, 0xD015, 0x00FF];
+        let quirks = CompatibilityProfile::SuperChip.into();
+        let mut machine = machine_with(quirks, &words);
+        for _ in 0..3 {
+            machine.tick().unwrap();
+        }
+        assert!(machine.is_hires());
+        // The "0" glyph's second row, 0x90, doubled into hires rows 2 and 3
+        for y in [2, 3] {
+            assert!(pixel(&machine, 0, y) && pixel(&machine, 1, y));
+            assert!((2..6).all(|x| !pixel(&machine, x, y)));
+            assert!(pixel(&machine, 6, y) && pixel(&machine, 7, y));
+        }
+        machine.set_hires(false);
+        assert_glyph_row(&machine, 0, 0, true);
+        assert!(pixel(&machine, 0, 1) && !pixel(&machine, 1, 1) && pixel(&machine, 3, 1));
+        assert!(pixel(&machine, 3, 4) && !pixel(&machine, 0, 5));
+
+        let machine = run(&words);
+        assert!(machine.is_hires());
+        assert!(machine.get_display().iter().all(|pixel| !pixel));
+    }
+
+    #[test]
+    fn breakpoints_are_listed_in_order() {
+        let mut machine = Machine::new();
+        machine.add_breakpoint(0x210);
+        machine.add_breakpoint(0x200);
+        machine.add_memory_watchpoint(0x300..0x310);
+        machine.add_memory_watchpoint(0x280..0x290);
+        assert_eq!(machine.breakpoints(), [0x200, 0x210]);
+        assert_eq!(machine.watchpoints(), [0x280..0x290, 0x300..0x310]);
+
+        machine.clear_breakpoints();
+        machine.clear_watchpoints();
+        assert!(machine.breakpoints().is_empty());
+        assert!(machine.watchpoints().is_empty());
+    }
+
+    #[test]
+    fn fault_hook_sees_errors_before_they_return() {
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+
+        let faults = Rc::new(RefCell::new(Vec::new()));
+        let mut machine = machine_with(Quirks::default(), &[0x6005, 0x00EE]);
+        let sink = Rc::clone(&faults);
+        machine.on_fault(Box::new(move |err, machine| {
+            sink.borrow_mut().push((*err, machine.registers()[0]))
+        }));
+        machine.tick().unwrap();
+        assert!(faults.borrow().is_empty());
+        assert_eq!(machine.tick(), Err(Chip8Error::StackUnderflow));
+        assert_eq!(*faults.borrow(), [(Chip8Error::StackUnderflow, 5)]);
+
+        machine.set_program_counter(0x202);
+        assert_eq!(machine.run_frame(), Err(Chip8Error::StackUnderflow));
+        assert_eq!(faults.borrow().len(), 2);
+    }
+
+    #[test]
+    fn invalid_opcodes_follow_the_policy() {
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+
+        let words = [0x8128, 0x0123, 0x6001];
+        let machine = run(&words);
+        assert_eq!(machine.registers()[0], 1);
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let quirks = Quirks {
+            invalid_opcodes: InvalidOpcodePolicy::Log,
+            ..Quirks::default()
+        };
+        let mut machine = machine_with(quirks, &words);
+        let sink = Rc::clone(&events);
+        machine.set_event_hook(Box::new(move |event| {
+            if let TraceEvent::InvalidOpcode { pc, opcode } = event {
+                sink.borrow_mut().push((*pc, *opcode));
             }
-            (_, _, _, _) => (),
+        }));
+        for _ in 0..3 {
+            machine.tick().unwrap();
+        }
+        assert_eq!(machine.registers()[0], 1);
+        assert_eq!(*events.borrow(), [(0x200, 0x8128), (0x202, 0x0123)]);
+
+        let quirks = Quirks {
+            invalid_opcodes: InvalidOpcodePolicy::Error,
+            ..Quirks::default()
         };
+        let mut machine = machine_with(quirks, &words);
+        assert_eq!(machine.tick(), Err(Chip8Error::InvalidOpcode(0x8128)));
+    }
+
+    #[test]
+    fn skips_step_over_long_loads() {
+        let words = [
+            0x6005, 0x3005, 0xF000, 0x0300, 0x4006, 0xF000, 0x0300, 0xE09E, 0xF000, 0x0300,
+        ];
+        let mut machine = machine_with(CompatibilityProfile::XoChip.into(), &words);
+        machine.keypress(5, true);
+        machine.tick().unwrap();
+        for pc in [0x208, 0x20E, 0x214] {
+            machine.tick().unwrap();
+            assert_eq!(machine.program_counter(), pc);
+        }
+
+        let mut machine = machine_with(Quirks::default(), &words);
+        machine.tick().unwrap();
+        machine.tick().unwrap();
+        assert_eq!(machine.program_counter(), 0x206);
+    }
+
+    #[test]
+    fn classify_memory_separates_code_from_data() {
+        use coverage::{MemRegion, MemoryUse};
+
+        let mut machine = machine_with(
+            Quirks::default(),
+            &[0x6000, 0xF029, 0xD015, 0xA300, 0x6007, 0xF033, 0x120C],
+        );
+        assert!(machine.classify_memory().is_empty());
+        machine.enable_coverage();
+        for _ in 0..8 {
+            machine.tick().unwrap();
+        }
+
+        assert_eq!(machine.font_address(0), 0);
+        let region = |range: Range<usize>, usage| MemRegion { range, usage };
+        assert_eq!(
+            machine.classify_memory(),
+            [
+                region(0..5, MemoryUse::Data),
+                region(5..0x200, MemoryUse::Untouched),
+                region(0x200..0x20E, MemoryUse::Code),
+                region(0x20E..0x300, MemoryUse::Untouched),
+                region(0x300..0x303, MemoryUse::Data),
+                region(0x303..RAM_SIZE, MemoryUse::Untouched),
+            ]
+        );
+    }
+
+    #[test]
+    fn run_checked_reports_the_first_divergence() {
+        use trace::TraceRecord;
+
+        let words = [0x6005, 0x7003, 0xA123];
+        let record = |pc, opcode, v0, i_reg| {
+            let mut v_reg = [0; NUM_REGS];
+            v_reg[0] = v0;
+            TraceRecord {
+                pc,
+                opcode,
+                v_reg,
+                i_reg,
+            }
+        };
+        let mut reference = [
+            record(0x200, 0x6005, 5, 0),
+            record(0x202, 0x7003, 8, 0),
+            record(0x204, 0xA123, 8, 0x123),
+        ];
+        let mut machine = machine_with(Quirks::default(), &words);
+        assert_eq!(machine.run_checked(&reference), Ok(()));
+
+        reference[1].v_reg[0] = 9;
+        let mut machine = machine_with(Quirks::default(), &words);
+        assert_eq!(
+            machine.run_checked(&reference),
+            Err((1, reference[1], record(0x202, 0x7003, 8, 0)))
+        );
+    }
+
+    #[test]
+    fn blocking_instructions_at_the_end_of_memory_stay_put() {
+        for opcode in [0x00FDu16, 0xF00A] {
+            let mut machine = Machine::new_with_quirks(CompatibilityProfile::XoChip.into());
+            machine.write_ram(0xFFFE, &opcode.to_be_bytes()).unwrap();
+            machine.set_program_counter(0xFFFE);
+            machine.tick().unwrap();
+            assert_eq!(machine.program_counter(), 0xFFFE);
+        }
+    }
+
+    #[test]
+    fn assembled_programs_disassemble_to_the_same_source() {
+        let source = "
+            start:
+                CLS
+                LD V0, 0x12
+                LD V1, V0
+                ADD V1, 3
+                ADD V1, V2
+                SE V0, 0x12
+                SNE V0, V1
+                OR V0, V1
+                AND V2, V3
+                XOR V4, V5
+                SUB V6, V7
+                SUBN V8, V9
+                SHR VA, VB
+                SHL VC, VD
+                LD I, sprite
+                ADD I, V3
+                RND V2, 0x0F
+                DRW V0, V1, 5
+                SKP V1
+                SKNP V2
+                LD V3, DT
+                LD V4, K
+                LD DT, V5
+                LD ST, V6
+                LD F, V7
+                LD HF, V8
+                LD B, V9
+                LD [I], VA
+                LD VB, [I]
+                LD R, VC
+                LD VD, R
+                SCD 4
+                SCR
+                SCL
+                LOW
+                HIGH
+                PLANE 3
+                AUDIO
+                CALL start
+                JP V0, start
+                JP start
+                RET
+                EXIT
+            sprite:
+                DW 0x5121";
+        let rom = asm::assemble(source).unwrap();
+        let lines = disassemble(&rom);
+        assert_eq!(lines[1], (0x202, String::from("LD V0, 0x12")));
+        assert_eq!(lines.last().unwrap().1, "DW 0x5121");
+
+        let listing: Vec<_> = lines.into_iter().map(|(_, text)| text).collect();
+        assert_eq!(asm::assemble(&listing.join("\n")), Ok(rom));
+    }
+
+    #[test]
+    fn assembler_errors_name_the_line() {
+        let line_of = |source: &str| asm::assemble(source).unwrap_err().line;
+        assert_eq!(line_of("CLS\n\nLD V0, 0x100"), 3);
+        assert_eq!(line_of("loop:\n  JP loop\nloop: CLS"), 3);
+        assert_eq!(line_of("; comment\nJP nowhere"), 2);
+        assert_eq!(line_of("CLS\nNOPE V1"), 2);
+        assert_eq!(line_of("LD I, a€x"), 1);
+        assert_eq!(line_of("LD I, LON€"), 1);
+        assert_eq!(
+            asm::assemble("LD I, long 0x1234"),
+            Ok(vec![0xF0, 0x00, 0x12, 0x34])
+        );
+    }
+
+    #[test]
+    fn states_with_another_memory_size_are_rejected() {
+        let machine = run(&[0x6001]);
+        let bytes = machine.save_state().to_bytes();
+        // Magic, PC, then the memory size and memory itself
+        let mut no_ram = bytes[..6].to_vec();
+        no_ram.extend_from_slice(&0u32.to_be_bytes());
+        no_ram.extend_from_slice(&bytes[10 + RAM_SIZE..]);
+        assert_eq!(MachineState::from_bytes(&no_ram), None);
+        assert_eq!(MachineState::from_bytes(&bytes[..100]), None);
+        assert_eq!(MachineState::from_bytes(&[]), None);
+
+        let mut xo = Machine::new_with_quirks(CompatibilityProfile::XoChip.into());
+        let ram = xo.ram_mut().as_mut_ptr();
+        assert_eq!(
+            xo.load_state(&machine.save_state()),
+            Err(Chip8Error::StateMismatch {
+                ram_len: RAM_SIZE,
+                expected: XO_RAM_SIZE
+            })
+        );
+        let state = xo.save_state();
+        xo.load_state(&state).unwrap();
+        assert_eq!(xo.ram_mut().as_mut_ptr(), ram);
+    }
+
+    #[test]
+    fn profiler_counts_per_address_and_family() {
+        use profile::OpcodeFamily;
+
+        let mut machine = machine_with(Quirks::default(), &[0x6001, 0x7001, 0x1202]);
+        assert!(machine.profile_report().hotspots.is_empty());
+        machine.enable_profiling();
+        for _ in 0..10 {
+            machine.tick().unwrap();
+        }
+
+        let report = machine.profile_report();
+        assert_eq!(report.total.count, 10);
+        let spots: Vec<_> = report
+            .hotspots
+            .iter()
+            .map(|spot| (spot.addr, spot.counter.count))
+            .collect();
+        assert_eq!(spots, [(0x202, 5), (0x204, 4), (0x200, 1)]);
+        assert_eq!(report.family(OpcodeFamily::Alu).count, 6);
+        assert_eq!(report.family(OpcodeFamily::ControlFlow).count, 4);
+        assert_eq!(report.family(OpcodeFamily::Draw).count, 0);
+
+        machine.disable_profiling();
+        assert_eq!(machine.profile_report().total.count, 0);
+    }
+
+    #[test]
+    fn vip_timing_spends_a_cycle_budget_per_frame() {
+        let quirks = Quirks {
+            vip_timing: true,
+            ..Quirks::default()
+        };
+        // Each jump costs 80 cycles of the 1832 in a frame
+        let mut machine = machine_with(quirks, &[0x1200]);
+        assert_eq!(machine.run_frame().unwrap().cycles, 23);
+
+        let mut machine = machine_with(quirks, &[0xD015, 0x1200]);
+        assert_eq!(machine.run_frame().unwrap().cycles, 1);
+    }
+
+    #[test]
+    fn advance_runs_whole_frames_of_time() {
+        let words = [0x6064, 0xF015, 0x7101, 0x1204];
+        let mut machine = machine_with(Quirks::default(), &words);
+        // 30 frames of 10 instructions
+        machine
+            .advance(core::time::Duration::from_millis(500))
+            .unwrap();
+        assert_eq!(machine.registers()[1], 149);
+        assert_eq!(machine.delay_timer(), 70);
+
+        let mut machine = machine_with(Quirks::default(), &words);
+        machine.advance_cycles(5).unwrap();
+        assert_eq!(machine.delay_timer(), 100);
+        machine.advance_cycles(5).unwrap();
+        assert_eq!(machine.delay_timer(), 99);
+        assert_eq!(machine.registers()[1], 4);
+    }
+
+    #[test]
+    fn lockstep_finds_the_first_diverging_frame() {
+        use lockstep::{Lockstep, LockstepError};
+
+        let words = [0xC0FF, 0x6102, 0x8216, 0x1200];
+        let pair = |first: Quirks, second: Quirks| {
+            let machines = [first, second]
+                .into_iter()
+                .map(|quirks| {
+                    let mut machine = machine_with(quirks, &words);
+                    machine.set_rng_seed(3);
+                    machine
+                })
+                .collect();
+            Lockstep::new(machines, Box::new(|frame: u64| frame as u16))
+        };
+
+        let mut same = pair(Quirks::default(), Quirks::default());
+        assert_eq!(same.run(20), Ok(()));
+        assert_eq!(same.frame(), 20);
+
+        let vip = Quirks {
+            shift_uses_vy: true,
+            ..Quirks::default()
+        };
+        let mut different = pair(Quirks::default(), vip);
+        assert_eq!(
+            different.run(20),
+            Err(LockstepError::Desync { frame: 0, index: 1 })
+        );
+    }
+
+    #[test]
+    fn rewind_goes_back_whole_frames() {
+        let mut machine = machine_with(Quirks::default(), &[0x7001, 0x1200]);
+        assert_eq!(machine.rewind(1), 0);
+        machine.enable_rewind(10);
+        for _ in 0..3 {
+            machine.run_frame().unwrap();
+        }
+        assert_eq!(machine.registers()[0], 15);
+        assert_eq!(machine.rewind_len(), 2);
+
+        assert_eq!(machine.rewind(1), 1);
+        assert_eq!(machine.registers()[0], 10);
+        assert_eq!(machine.rewind(5), 1);
+        assert_eq!(machine.registers()[0], 5);
+        assert_eq!(machine.rewind_len(), 0);
+
+        machine.run_frame().unwrap();
+        machine.set_quirks(CompatibilityProfile::XoChip.into());
+        assert_eq!(machine.rewind_len(), 0);
+    }
+
+    #[test]
+    fn recordings_replay_to_the_same_state() {
+        let words = [0xC00F, 0xE09E, 0x7101, 0x1200];
+        let mut recorded = machine_with(Quirks::default(), &words);
+        recorded.start_recording(42);
+        for frame in 0..30u16 {
+            recorded.set_keys_held_mask(frame.wrapping_mul(0x9E37));
+            recorded.run_frame().unwrap();
+        }
+        let recording = recorded.stop_recording().unwrap();
+        assert_eq!(recording.frames.len(), 30);
+        assert_eq!(recording.seed, 42);
+        assert!(recorded.stop_recording().is_none());
+
+        let mut replayed = machine_with(Quirks::default(), &words);
+        replayed.play_recording(&recording).unwrap();
+        assert_ne!(replayed.registers()[1], 0);
+        assert_eq!(replayed.save_state(), recorded.save_state());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn emulator_thread_reports_load_errors() {
+        use rom::Rom;
+        use thread::{EmulatorThread, ThreadConfig};
+
+        let config = ThreadConfig {
+            start_paused: true,
+            ..ThreadConfig::default()
+        };
+        let too_large = Rom::from_bytes(&[0; 4000]).unwrap();
+        assert!(matches!(
+            EmulatorThread::spawn(too_large, config),
+            Err(Chip8Error::RomTooLarge { size: 4000, .. })
+        ));
+
+        let rom = Rom::from_bytes(&[0x60, 0x2A, 0x12, 0x02]).unwrap();
+        let emulator = EmulatorThread::spawn(rom, config).unwrap();
+        assert!(emulator.is_paused());
+        assert!(emulator.save_state().is_some());
+    }
+
+    #[test]
+    fn intel_hex_records_carry_checksums() {
+        use memio::{parse_intel_hex, to_intel_hex};
+
+        let text = to_intel_hex(0x0100, &[0x21, 0x46, 0x01]);
+        assert_eq!(text, ":0301000021460194\n:00000001FF\n");
+        assert_eq!(
+            parse_intel_hex(&text),
+            Ok(vec![(0x0100, vec![0x21, 0x46, 0x01])])
+        );
+    }
+
+    #[test]
+    fn intel_hex_extended_addresses() {
+        use memio::{parse_intel_hex, to_intel_hex};
+
+        let data: Vec<u8> = (0..32).collect();
+        let text = to_intel_hex(0xFFF0, &data);
+        assert_eq!(text.lines().nth(1), Some(":020000040001F9"));
+        assert_eq!(
+            parse_intel_hex(&text),
+            Ok(vec![
+                (0xFFF0, data[..16].to_vec()),
+                (0x10000, data[16..].to_vec())
+            ])
+        );
+
+        let segmented = ":020000021000EC\n:02001000ABCD76\n:00000001FF\n";
+        assert_eq!(
+            parse_intel_hex(segmented),
+            Ok(vec![(0x10010, vec![0xAB, 0xCD])])
+        );
+    }
+
+    #[test]
+    fn intel_hex_round_trips_all_of_xo_chip_memory() {
+        let image: Vec<u8> = (0..XO_RAM_SIZE)
+            .map(|addr| ((addr * 7) >> 3) as u8)
+            .collect();
+        let mut source = Machine::new_with_quirks(CompatibilityProfile::XoChip.into());
+        source.write_ram(0, &image).unwrap();
+        let text = source.export_intel_hex(0..XO_RAM_SIZE);
+
+        let mut target = Machine::new_with_quirks(CompatibilityProfile::XoChip.into());
+        target.import_intel_hex(&text).unwrap();
+        assert_eq!(target.ram(), &image[..]);
+        assert_eq!(
+            Machine::new().import_intel_hex(&text),
+            Err(memio::HexError::Machine(Chip8Error::MemoryOutOfBounds {
+                addr: RAM_SIZE
+            }))
+        );
+    }
+
+    #[test]
+    fn intel_hex_rejects_bad_checksums() {
+        let text = ":02030000ABCD83\n:0301000021460195\n:00000001FF\n";
+        let mut machine = Machine::new();
+        let before = machine.ram().to_vec();
+        assert_eq!(
+            machine.import_intel_hex(text),
+            Err(memio::HexError::Checksum { line: 2 })
+        );
+        assert_eq!(machine.ram(), &before[..]);
     }
 }
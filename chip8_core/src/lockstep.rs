@@ -0,0 +1,132 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::fmt;
+
+use crate::{Chip8Error, Machine};
+
+/// Decides which keys every machine holds during a frame, for example by
+/// merging local input with a peer's over the network before returning.
+pub trait InputExchange {
+    /// Keys held during `frame` (counted from 0) as a `keys_held_mask`.
+    fn exchange(&mut self, frame: u64) -> u16;
+}
+
+impl<F: FnMut(u64) -> u16> InputExchange for F {
+    fn exchange(&mut self, frame: u64) -> u16 {
+        self(frame)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockstepError {
+    /// Machine `index` failed to run `frame`.
+    Machine {
+        frame: u64,
+        index: usize,
+        error: Chip8Error,
+    },
+    /// After `frame`, machine `index` no longer matches machine 0.
+    Desync { frame: u64, index: usize },
+}
+
+impl fmt::Display for LockstepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockstepError::Machine {
+                frame,
+                index,
+                error,
+            } => write!(f, "machine {} failed in frame {}: {}", index, frame, error),
+            LockstepError::Desync { frame, index } => {
+                write!(
+                    f,
+                    "machine {} diverged from machine 0 in frame {}",
+                    index, frame
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LockstepError {}
+
+/// Runs several machines frame by frame on the same input and checks after
+/// every frame that their states still match.
+///
+/// With the same ROM and RNG seed everywhere this catches nondeterminism,
+/// which would break netplay; with different quirks it finds the first frame
+/// where two profiles behave differently.
+pub struct Lockstep {
+    machines: Vec<Machine>,
+    input: Box<dyn InputExchange>,
+    frame: u64,
+}
+
+impl Lockstep {
+    pub fn new(machines: Vec<Machine>, input: Box<dyn InputExchange>) -> Self {
+        Self {
+            machines,
+            input,
+            frame: 0,
+        }
+    }
+
+    pub fn machines(&self) -> &[Machine] {
+        &self.machines
+    }
+
+    pub fn machines_mut(&mut self) -> &mut [Machine] {
+        &mut self.machines
+    }
+
+    pub fn into_machines(self) -> Vec<Machine> {
+        self.machines
+    }
+
+    /// Frames advanced so far.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Runs one `run_frame` on every machine with the exchanged input, then
+    /// compares their states.
+    pub fn advance(&mut self) -> Result<(), LockstepError> {
+        let frame = self.frame;
+        let keys = self.input.exchange(frame);
+        for (index, machine) in self.machines.iter_mut().enumerate() {
+            machine.set_keys_held_mask(keys);
+            machine
+                .run_frame()
+                .map_err(|error| LockstepError::Machine {
+                    frame,
+                    index,
+                    error,
+                })?;
+        }
+        self.frame += 1;
+
+        let Some((first, rest)) = self.machines.split_first() else {
+            return Ok(());
+        };
+        let expected = first.save_state();
+        match rest
+            .iter()
+            .position(|machine| machine.save_state() != expected)
+        {
+            Some(i) => Err(LockstepError::Desync {
+                frame,
+                index: i + 1,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Advances `frames` times, stopping at the first error.
+    pub fn run(&mut self, frames: u64) -> Result<(), LockstepError> {
+        for _ in 0..frames {
+            self.advance()?;
+        }
+
+        Ok(())
+    }
+}
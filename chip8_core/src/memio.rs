@@ -0,0 +1,179 @@
+use alloc::{string::String, vec::Vec};
+use core::{fmt, fmt::Write, ops::Range};
+
+use crate::{Chip8Error, Machine};
+
+// Data bytes per Intel HEX record, as most tools write them
+const HEX_RECORD_LEN: usize = 16;
+
+const RECORD_DATA: u8 = 0x00;
+const RECORD_EOF: u8 = 0x01;
+const RECORD_SEGMENT: u8 = 0x02;
+const RECORD_LINEAR: u8 = 0x04;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HexError {
+    /// Line `line`, counted from 1, isn't a well-formed record.
+    Syntax { line: usize },
+    /// Line `line` has a wrong checksum.
+    Checksum { line: usize },
+    /// The data doesn't fit in memory.
+    Machine(Chip8Error),
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HexError::Syntax { line } => write!(f, "line {}: malformed Intel HEX record", line),
+            HexError::Checksum { line } => write!(f, "line {}: checksum mismatch", line),
+            HexError::Machine(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HexError {}
+
+impl From<Chip8Error> for HexError {
+    fn from(err: Chip8Error) -> Self {
+        HexError::Machine(err)
+    }
+}
+
+/// Encodes `data`, loaded at `addr`, as Intel HEX text ending in an EOF
+/// record.
+pub fn to_intel_hex(addr: usize, data: &[u8]) -> String {
+    let mut out = String::new();
+    let mut upper = 0;
+    for (i, chunk) in data.chunks(HEX_RECORD_LEN).enumerate() {
+        let start = addr + i * HEX_RECORD_LEN;
+        // Records only hold 16 address bits; the rest go in a linear address
+        // record whenever they change
+        if start >> 16 != upper {
+            upper = start >> 16;
+            write_record(&mut out, 0, RECORD_LINEAR, &(upper as u16).to_be_bytes());
+        }
+        write_record(&mut out, start as u16, RECORD_DATA, chunk);
+    }
+    write_record(&mut out, 0, RECORD_EOF, &[]);
+
+    out
+}
+
+fn write_record(out: &mut String, addr: u16, kind: u8, data: &[u8]) {
+    let mut bytes = Vec::with_capacity(data.len() + 4);
+    bytes.push(data.len() as u8);
+    bytes.extend_from_slice(&addr.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend_from_slice(data);
+    let checksum = bytes
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        .wrapping_neg();
+
+    out.push(':');
+    for byte in bytes.iter().chain([&checksum]) {
+        let _ = write!(out, "{:02X}", byte);
+    }
+    out.push('\n');
+}
+
+/// Decodes Intel HEX text into `(addr, data)` blocks, one per data record, up
+/// to the EOF record or the end of the text. Blank lines are skipped and start
+/// address records ignored.
+pub fn parse_intel_hex(text: &str) -> Result<Vec<(usize, Vec<u8>)>, HexError> {
+    let mut blocks = Vec::new();
+    let mut base = 0;
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let syntax = HexError::Syntax { line: line_number };
+        let hex = line.strip_prefix(':').ok_or(syntax)?;
+        if hex.len() % 2 != 0 || !hex.is_ascii() {
+            return Err(syntax);
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| syntax))
+            .collect::<Result<Vec<_>, _>>()?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(syntax);
+        }
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(HexError::Checksum { line: line_number });
+        }
+
+        let addr = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+        let data = &bytes[4..bytes.len() - 1];
+        match bytes[3] {
+            RECORD_DATA => blocks.push((base + addr, data.to_vec())),
+            RECORD_EOF => break,
+            RECORD_SEGMENT | RECORD_LINEAR => {
+                let [high, low] = data else {
+                    return Err(syntax);
+                };
+                let value = u16::from_be_bytes([*high, *low]) as usize;
+                base = if bytes[3] == RECORD_SEGMENT {
+                    value << 4
+                } else {
+                    value << 16
+                };
+            }
+            _ => {}
+        }
+    }
+
+    Ok(blocks)
+}
+
+impl Machine {
+    /// A copy of the RAM in `range`, clipped to the end of memory. Peripherals
+    /// mapped with `map_io` aren't read.
+    pub fn dump_ram(&self, range: Range<usize>) -> Vec<u8> {
+        let end = range.end.min(self.ram.len());
+        self.ram[range.start.min(end)..end].to_vec()
+    }
+
+    /// Copies `data` into RAM at `addr`, failing without writing anything if
+    /// it runs past the end of memory.
+    pub fn write_ram(&mut self, addr: usize, data: &[u8]) -> Result<(), Chip8Error> {
+        let end = addr.saturating_add(data.len());
+        if end > self.ram.len() {
+            return Err(Chip8Error::MemoryOutOfBounds {
+                addr: addr.max(self.ram.len()),
+            });
+        }
+        self.ram[addr..end].copy_from_slice(data);
+
+        Ok(())
+    }
+
+    /// `dump_ram(range)` as Intel HEX.
+    pub fn export_intel_hex(&self, range: Range<usize>) -> String {
+        let start = range.start.min(self.ram.len());
+        to_intel_hex(start, &self.dump_ram(range))
+    }
+
+    /// Writes every data record in `text` to RAM. Nothing is written unless
+    /// the whole file parses and fits.
+    pub fn import_intel_hex(&mut self, text: &str) -> Result<(), HexError> {
+        let blocks = parse_intel_hex(text)?;
+        for (addr, data) in &blocks {
+            if addr + data.len() > self.ram.len() {
+                return Err(Chip8Error::MemoryOutOfBounds {
+                    addr: (*addr).max(self.ram.len()),
+                }
+                .into());
+            }
+        }
+        for (addr, data) in &blocks {
+            self.write_ram(*addr, data)?;
+        }
+
+        Ok(())
+    }
+}
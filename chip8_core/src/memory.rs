@@ -0,0 +1,44 @@
+use core::ops::Range;
+
+use crate::{Chip8Error, Machine};
+
+impl Machine {
+    /// Reads the byte at `addr`, wrapping or failing past the end of memory
+    /// depending on the `wrap_memory` quirk.
+    pub fn read8(&self, addr: u16) -> Result<u8, Chip8Error> {
+        let span = self.mem_span(addr, 1)?;
+        Ok(self.ram[self.mem_index(span.start)])
+    }
+
+    pub fn write8(&mut self, addr: u16, value: u8) -> Result<(), Chip8Error> {
+        let span = self.mem_span(addr, 1)?;
+        let index = self.mem_index(span.start);
+        self.bus_write(index, value);
+
+        Ok(())
+    }
+
+    /// Big-endian word at `addr`, as instructions are stored.
+    pub fn read16(&self, addr: u16) -> Result<u16, Chip8Error> {
+        let span = self.mem_span(addr, 2)?;
+        let high = self.ram[self.mem_index(span.start)] as u16;
+        let low = self.ram[self.mem_index(span.start + 1)] as u16;
+
+        Ok((high << 8) | low)
+    }
+
+    /// `len` bytes from `start` as addresses before wrapping; index them
+    /// through `mem_index`.
+    pub(crate) fn mem_span(&self, start: u16, len: usize) -> Result<Range<usize>, Chip8Error> {
+        if self.quirks.wrap_memory {
+            let start = start as usize;
+            return Ok(start..start + len);
+        }
+
+        self.ram_range(start, len)
+    }
+
+    pub(crate) fn mem_index(&self, addr: usize) -> usize {
+        addr % self.ram.len()
+    }
+}
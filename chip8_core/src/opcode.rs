@@ -0,0 +1,236 @@
+use core::fmt;
+
+/// A decoded instruction. Register operands are indices into V0..VF and
+/// `Display` gives the usual Cowgod-style mnemonic. The interpreter executes
+/// this enum, so other backends can match on it instead of re-parsing nibbles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    ClearScreen,
+    Return,
+    ScrollDown(u8),
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    LowRes,
+    HighRes,
+    Jump(u16),
+    Call(u16),
+    SkipEqImm { x: usize, nn: u8 },
+    SkipNeImm { x: usize, nn: u8 },
+    SkipEqReg { x: usize, y: usize },
+    LoadImm { x: usize, nn: u8 },
+    AddImm { x: usize, nn: u8 },
+    LoadReg { x: usize, y: usize },
+    Or { x: usize, y: usize },
+    And { x: usize, y: usize },
+    Xor { x: usize, y: usize },
+    AddReg { x: usize, y: usize },
+    SubReg { x: usize, y: usize },
+    ShiftRight { x: usize, y: usize },
+    SubNReg { x: usize, y: usize },
+    ShiftLeft { x: usize, y: usize },
+    SkipNeReg { x: usize, y: usize },
+    LoadIndex(u16),
+    LoadLongIndex,
+    JumpOffset { x: usize, addr: u16 },
+    Random { x: usize, nn: u8 },
+    Draw { x: usize, y: usize, n: u8 },
+    SkipKeyPressed { x: usize },
+    SkipKeyNotPressed { x: usize },
+    LoadDelay { x: usize },
+    WaitKey { x: usize },
+    SetDelay { x: usize },
+    SetSound { x: usize },
+    AddIndex { x: usize },
+    LoadFont { x: usize },
+    LoadBigFont { x: usize },
+    StoreBcd { x: usize },
+    StoreRegs { x: usize },
+    LoadRegs { x: usize },
+    StoreFlags { x: usize },
+    LoadFlags { x: usize },
+    SelectPlanes(u8),
+    LoadAudioPattern,
+    Unknown(u16),
+}
+
+impl Opcode {
+    pub fn decode(op: u16) -> Self {
+        let byte1 = (op & 0xF000) >> 12;
+        let byte2 = (op & 0x0F00) >> 8;
+        let byte3 = (op & 0x00F0) >> 4;
+        let byte4 = op & 0x000F;
+
+        let x = byte2 as usize;
+        let y = byte3 as usize;
+        let nn = (op & 0xFF) as u8;
+        let addr = op & 0xFFF;
+
+        match (byte1, byte2, byte3, byte4) {
+            (0, 0, 0xE, 0) => Opcode::ClearScreen,
+            (0, 0, 0xE, 0xE) => Opcode::Return,
+            (0, 0, 0xC, _) => Opcode::ScrollDown(byte4 as u8),
+            (0, 0, 0xF, 0xB) => Opcode::ScrollRight,
+            (0, 0, 0xF, 0xC) => Opcode::ScrollLeft,
+            (0, 0, 0xF, 0xD) => Opcode::Exit,
+            (0, 0, 0xF, 0xE) => Opcode::LowRes,
+            (0, 0, 0xF, 0xF) => Opcode::HighRes,
+            (1, _, _, _) => Opcode::Jump(addr),
+            (2, _, _, _) => Opcode::Call(addr),
+            (3, _, _, _) => Opcode::SkipEqImm { x, nn },
+            (4, _, _, _) => Opcode::SkipNeImm { x, nn },
+            (5, _, _, 0) => Opcode::SkipEqReg { x, y },
+            (6, _, _, _) => Opcode::LoadImm { x, nn },
+            (7, _, _, _) => Opcode::AddImm { x, nn },
+            (8, _, _, 0) => Opcode::LoadReg { x, y },
+            (8, _, _, 1) => Opcode::Or { x, y },
+            (8, _, _, 2) => Opcode::And { x, y },
+            (8, _, _, 3) => Opcode::Xor { x, y },
+            (8, _, _, 4) => Opcode::AddReg { x, y },
+            (8, _, _, 5) => Opcode::SubReg { x, y },
+            (8, _, _, 6) => Opcode::ShiftRight { x, y },
+            (8, _, _, 7) => Opcode::SubNReg { x, y },
+            (8, _, _, 0xE) => Opcode::ShiftLeft { x, y },
+            (9, _, _, 0) => Opcode::SkipNeReg { x, y },
+            (0xA, _, _, _) => Opcode::LoadIndex(addr),
+            (0xB, _, _, _) => Opcode::JumpOffset { x, addr },
+            (0xC, _, _, _) => Opcode::Random { x, nn },
+            (0xD, _, _, _) => Opcode::Draw {
+                x,
+                y,
+                n: byte4 as u8,
+            },
+            (0xE, _, 9, 0xE) => Opcode::SkipKeyPressed { x },
+            (0xE, _, 0xA, 1) => Opcode::SkipKeyNotPressed { x },
+            (0xF, _, 0, 7) => Opcode::LoadDelay { x },
+            (0xF, _, 0, 0xA) => Opcode::WaitKey { x },
+            (0xF, _, 1, 5) => Opcode::SetDelay { x },
+            (0xF, _, 1, 8) => Opcode::SetSound { x },
+            (0xF, _, 1, 0xE) => Opcode::AddIndex { x },
+            (0xF, _, 2, 9) => Opcode::LoadFont { x },
+            (0xF, _, 3, 0) => Opcode::LoadBigFont { x },
+            (0xF, _, 3, 3) => Opcode::StoreBcd { x },
+            (0xF, _, 5, 5) => Opcode::StoreRegs { x },
+            (0xF, _, 6, 5) => Opcode::LoadRegs { x },
+            (0xF, _, 7, 5) => Opcode::StoreFlags { x },
+            (0xF, _, 8, 5) => Opcode::LoadFlags { x },
+            (0xF, 0, 0, 0) => Opcode::LoadLongIndex,
+            (0xF, 0, 0, 2) => Opcode::LoadAudioPattern,
+            (0xF, _, 0, 1) => Opcode::SelectPlanes(byte2 as u8),
+            (_, _, _, _) => Opcode::Unknown(op),
+        }
+    }
+}
+
+impl Opcode {
+    /// Inverse of `decode`. `LoadLongIndex` encodes only its first word; the
+    /// address follows it in memory.
+    pub fn encode(&self) -> u16 {
+        let xy = |op: u16, x: usize, y: usize| op | (x as u16) << 8 | (y as u16) << 4;
+        let xnn = |op: u16, x: usize, nn: u8| op | (x as u16) << 8 | nn as u16;
+        let fx = |low: u16, x: usize| 0xF000 | (x as u16) << 8 | low;
+        match *self {
+            Opcode::ClearScreen => 0x00E0,
+            Opcode::Return => 0x00EE,
+            Opcode::ScrollDown(n) => 0x00C0 | (n & 0xF) as u16,
+            Opcode::ScrollRight => 0x00FB,
+            Opcode::ScrollLeft => 0x00FC,
+            Opcode::Exit => 0x00FD,
+            Opcode::LowRes => 0x00FE,
+            Opcode::HighRes => 0x00FF,
+            Opcode::Jump(addr) => 0x1000 | addr & 0xFFF,
+            Opcode::Call(addr) => 0x2000 | addr & 0xFFF,
+            Opcode::SkipEqImm { x, nn } => xnn(0x3000, x, nn),
+            Opcode::SkipNeImm { x, nn } => xnn(0x4000, x, nn),
+            Opcode::SkipEqReg { x, y } => xy(0x5000, x, y),
+            Opcode::LoadImm { x, nn } => xnn(0x6000, x, nn),
+            Opcode::AddImm { x, nn } => xnn(0x7000, x, nn),
+            Opcode::LoadReg { x, y } => xy(0x8000, x, y),
+            Opcode::Or { x, y } => xy(0x8001, x, y),
+            Opcode::And { x, y } => xy(0x8002, x, y),
+            Opcode::Xor { x, y } => xy(0x8003, x, y),
+            Opcode::AddReg { x, y } => xy(0x8004, x, y),
+            Opcode::SubReg { x, y } => xy(0x8005, x, y),
+            Opcode::ShiftRight { x, y } => xy(0x8006, x, y),
+            Opcode::SubNReg { x, y } => xy(0x8007, x, y),
+            Opcode::ShiftLeft { x, y } => xy(0x800E, x, y),
+            Opcode::SkipNeReg { x, y } => xy(0x9000, x, y),
+            Opcode::LoadIndex(addr) => 0xA000 | addr & 0xFFF,
+            Opcode::JumpOffset { addr, .. } => 0xB000 | addr & 0xFFF,
+            Opcode::Random { x, nn } => xnn(0xC000, x, nn),
+            Opcode::Draw { x, y, n } => xy(0xD000, x, y) | (n & 0xF) as u16,
+            Opcode::SkipKeyPressed { x } => xnn(0xE000, x, 0x9E),
+            Opcode::SkipKeyNotPressed { x } => xnn(0xE000, x, 0xA1),
+            Opcode::LoadDelay { x } => fx(0x07, x),
+            Opcode::WaitKey { x } => fx(0x0A, x),
+            Opcode::SetDelay { x } => fx(0x15, x),
+            Opcode::SetSound { x } => fx(0x18, x),
+            Opcode::AddIndex { x } => fx(0x1E, x),
+            Opcode::LoadFont { x } => fx(0x29, x),
+            Opcode::LoadBigFont { x } => fx(0x30, x),
+            Opcode::StoreBcd { x } => fx(0x33, x),
+            Opcode::StoreRegs { x } => fx(0x55, x),
+            Opcode::LoadRegs { x } => fx(0x65, x),
+            Opcode::StoreFlags { x } => fx(0x75, x),
+            Opcode::LoadFlags { x } => fx(0x85, x),
+            Opcode::LoadLongIndex => 0xF000,
+            Opcode::SelectPlanes(mask) => fx(0x01, (mask & 0xF) as usize),
+            Opcode::LoadAudioPattern => 0xF002,
+            Opcode::Unknown(op) => op,
+        }
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Opcode::ClearScreen => write!(f, "CLS"),
+            Opcode::Return => write!(f, "RET"),
+            Opcode::ScrollDown(n) => write!(f, "SCD {}", n),
+            Opcode::ScrollRight => write!(f, "SCR"),
+            Opcode::ScrollLeft => write!(f, "SCL"),
+            Opcode::Exit => write!(f, "EXIT"),
+            Opcode::LowRes => write!(f, "LOW"),
+            Opcode::HighRes => write!(f, "HIGH"),
+            Opcode::Jump(addr) => write!(f, "JP {:#05X}", addr),
+            Opcode::Call(addr) => write!(f, "CALL {:#05X}", addr),
+            Opcode::SkipEqImm { x, nn } => write!(f, "SE V{:X}, {:#04X}", x, nn),
+            Opcode::SkipNeImm { x, nn } => write!(f, "SNE V{:X}, {:#04X}", x, nn),
+            Opcode::SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Opcode::LoadImm { x, nn } => write!(f, "LD V{:X}, {:#04X}", x, nn),
+            Opcode::AddImm { x, nn } => write!(f, "ADD V{:X}, {:#04X}", x, nn),
+            Opcode::LoadReg { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Opcode::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Opcode::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Opcode::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Opcode::AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Opcode::SubReg { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Opcode::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Opcode::SubNReg { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Opcode::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Opcode::SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Opcode::LoadIndex(addr) => write!(f, "LD I, {:#05X}", addr),
+            Opcode::JumpOffset { addr, .. } => write!(f, "JP V0, {:#05X}", addr),
+            Opcode::Random { x, nn } => write!(f, "RND V{:X}, {:#04X}", x, nn),
+            Opcode::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Opcode::SkipKeyPressed { x } => write!(f, "SKP V{:X}", x),
+            Opcode::SkipKeyNotPressed { x } => write!(f, "SKNP V{:X}", x),
+            Opcode::LoadDelay { x } => write!(f, "LD V{:X}, DT", x),
+            Opcode::WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            Opcode::SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            Opcode::SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            Opcode::AddIndex { x } => write!(f, "ADD I, V{:X}", x),
+            Opcode::LoadFont { x } => write!(f, "LD F, V{:X}", x),
+            Opcode::LoadBigFont { x } => write!(f, "LD HF, V{:X}", x),
+            Opcode::StoreBcd { x } => write!(f, "LD B, V{:X}", x),
+            Opcode::StoreRegs { x } => write!(f, "LD [I], V{:X}", x),
+            Opcode::LoadRegs { x } => write!(f, "LD V{:X}, [I]", x),
+            Opcode::StoreFlags { x } => write!(f, "LD R, V{:X}", x),
+            Opcode::LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+            Opcode::SelectPlanes(mask) => write!(f, "PLANE {}", mask),
+            Opcode::LoadLongIndex => write!(f, "LD I, LONG"),
+            Opcode::LoadAudioPattern => write!(f, "AUDIO"),
+            Opcode::Unknown(op) => write!(f, "DW {:#06X}", op),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use alloc::{collections::BTreeMap, format, string::ToString, vec::Vec};
+use core::fmt;
+
+use crate::{opcode::Opcode, timing::vip_cycles, Machine};
+
+// Hotspots listed by the `Display` impl
+const REPORT_HOTSPOTS: usize = 10;
+
+/// Broad instruction groups, to see where a program spends its frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OpcodeFamily {
+    /// Sprites, clears, scrolls and display mode changes.
+    Draw,
+    /// Register arithmetic, logic, loads and `RND`.
+    Alu,
+    /// Jumps, calls, returns and skips.
+    ControlFlow,
+    /// `I` updates and memory loads and stores.
+    Memory,
+    /// Timers and the keypad.
+    TimersAndInput,
+    Unknown,
+}
+
+impl OpcodeFamily {
+    pub const ALL: [Self; 6] = [
+        Self::Draw,
+        Self::Alu,
+        Self::ControlFlow,
+        Self::Memory,
+        Self::TimersAndInput,
+        Self::Unknown,
+    ];
+
+    pub fn of(op: Opcode) -> Self {
+        match op {
+            Opcode::ClearScreen
+            | Opcode::ScrollDown(_)
+            | Opcode::ScrollRight
+            | Opcode::ScrollLeft
+            | Opcode::LowRes
+            | Opcode::HighRes
+            | Opcode::Draw { .. }
+            | Opcode::SelectPlanes(_) => Self::Draw,
+            Opcode::LoadImm { .. }
+            | Opcode::AddImm { .. }
+            | Opcode::LoadReg { .. }
+            | Opcode::Or { .. }
+            | Opcode::And { .. }
+            | Opcode::Xor { .. }
+            | Opcode::AddReg { .. }
+            | Opcode::SubReg { .. }
+            | Opcode::ShiftRight { .. }
+            | Opcode::SubNReg { .. }
+            | Opcode::ShiftLeft { .. }
+            | Opcode::Random { .. } => Self::Alu,
+            Opcode::Return
+            | Opcode::Exit
+            | Opcode::Jump(_)
+            | Opcode::Call(_)
+            | Opcode::JumpOffset { .. }
+            | Opcode::SkipEqImm { .. }
+            | Opcode::SkipNeImm { .. }
+            | Opcode::SkipEqReg { .. }
+            | Opcode::SkipNeReg { .. } => Self::ControlFlow,
+            Opcode::LoadIndex(_)
+            | Opcode::LoadLongIndex
+            | Opcode::AddIndex { .. }
+            | Opcode::LoadFont { .. }
+            | Opcode::LoadBigFont { .. }
+            | Opcode::StoreBcd { .. }
+            | Opcode::StoreRegs { .. }
+            | Opcode::LoadRegs { .. }
+            | Opcode::StoreFlags { .. }
+            | Opcode::LoadFlags { .. }
+            | Opcode::LoadAudioPattern => Self::Memory,
+            Opcode::SkipKeyPressed { .. }
+            | Opcode::SkipKeyNotPressed { .. }
+            | Opcode::LoadDelay { .. }
+            | Opcode::WaitKey { .. }
+            | Opcode::SetDelay { .. }
+            | Opcode::SetSound { .. } => Self::TimersAndInput,
+            Opcode::Unknown(_) => Self::Unknown,
+        }
+    }
+
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|family| *family == self).unwrap()
+    }
+}
+
+/// How often something executed and roughly how long it took, in COSMAC VIP
+/// machine cycles. Instructions the VIP doesn't have only count its fetch and
+/// decode overhead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ProfileCounter {
+    pub count: u64,
+    pub cycles: u64,
+}
+
+impl ProfileCounter {
+    fn add(&mut self, cycles: u32) {
+        self.count += 1;
+        self.cycles += cycles as u64;
+    }
+}
+
+/// Executions of the instruction at one address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hotspot {
+    pub addr: u16,
+    /// The opcode last executed there, which only differs from earlier ones
+    /// in self-modifying programs.
+    pub opcode: u16,
+    pub counter: ProfileCounter,
+}
+
+/// Everything counted since profiling was enabled.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ProfileReport {
+    pub total: ProfileCounter,
+    /// Every address that executed, most cycles first.
+    pub hotspots: Vec<Hotspot>,
+    /// One entry per family in `OpcodeFamily::ALL` order.
+    pub families: Vec<(OpcodeFamily, ProfileCounter)>,
+}
+
+impl ProfileReport {
+    pub fn family(&self, family: OpcodeFamily) -> ProfileCounter {
+        self.families
+            .iter()
+            .find(|(f, _)| *f == family)
+            .map(|(_, counter)| *counter)
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = |cycles: u64| cycles as f64 * 100.0 / self.total.cycles.max(1) as f64;
+
+        writeln!(
+            f,
+            "{} instructions, {} cycles",
+            self.total.count, self.total.cycles
+        )?;
+        for (family, counter) in &self.families {
+            writeln!(
+                f,
+                "  {:<16} {:>10} {:>12} {:>5.1}%",
+                format!("{:?}", family),
+                counter.count,
+                counter.cycles,
+                percent(counter.cycles)
+            )?;
+        }
+        writeln!(f, "Hotspots:")?;
+        for spot in self.hotspots.iter().take(REPORT_HOTSPOTS) {
+            writeln!(
+                f,
+                "  {:#05X}  {:<16} {:>10} {:>12} {:>5.1}%",
+                spot.addr,
+                Opcode::decode(spot.opcode).to_string(),
+                spot.counter.count,
+                spot.counter.cycles,
+                percent(spot.counter.cycles)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Profiler {
+    addrs: BTreeMap<u16, (u16, ProfileCounter)>,
+    families: [ProfileCounter; OpcodeFamily::ALL.len()],
+}
+
+impl Machine {
+    /// Starts counting every executed instruction per address and per
+    /// `OpcodeFamily`, discarding earlier counts.
+    pub fn enable_profiling(&mut self) {
+        self.profiler = Some(Profiler::default());
+    }
+
+    pub fn disable_profiling(&mut self) {
+        self.profiler = None;
+    }
+
+    pub fn is_profiling(&self) -> bool {
+        self.profiler.is_some()
+    }
+
+    /// Counts so far; empty while profiling is disabled.
+    pub fn profile_report(&self) -> ProfileReport {
+        let Some(profiler) = &self.profiler else {
+            return ProfileReport::default();
+        };
+
+        let mut hotspots: Vec<_> = profiler
+            .addrs
+            .iter()
+            .map(|(addr, (opcode, counter))| Hotspot {
+                addr: *addr,
+                opcode: *opcode,
+                counter: *counter,
+            })
+            .collect();
+        hotspots.sort_by_key(|spot| core::cmp::Reverse(spot.counter.cycles));
+
+        let families: Vec<_> = OpcodeFamily::ALL
+            .into_iter()
+            .zip(profiler.families)
+            .collect();
+        let total = families
+            .iter()
+            .fold(ProfileCounter::default(), |total, (_, counter)| {
+                ProfileCounter {
+                    count: total.count + counter.count,
+                    cycles: total.cycles + counter.cycles,
+                }
+            });
+
+        ProfileReport {
+            total,
+            hotspots,
+            families,
+        }
+    }
+
+    pub(crate) fn record_profile(&mut self, pc: u16, op: u16) {
+        let Some(profiler) = self.profiler.as_mut() else {
+            return;
+        };
+
+        let decoded = Opcode::decode(op);
+        let cycles = vip_cycles(decoded);
+        let entry = profiler.addrs.entry(pc).or_default();
+        entry.0 = op;
+        entry.1.add(cycles);
+        profiler.families[OpcodeFamily::of(decoded).index()].add(cycles);
+    }
+}
@@ -0,0 +1,54 @@
+use alloc::vec::Vec;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{Chip8Error, Machine};
+
+/// Keys held at the start of every `run_frame`, plus what is needed to make
+/// the run reproducible.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InputRecording {
+    pub seed: u64,
+    pub speed: usize,
+    /// One `keys_held_mask` per frame.
+    pub frames: Vec<u16>,
+}
+
+impl Machine {
+    /// Reseeds the RNG with `seed` and starts recording input frame by frame.
+    /// Start right after loading the ROM so playback begins from the same
+    /// state.
+    pub fn start_recording(&mut self, seed: u64) {
+        self.set_rng_seed(seed);
+        self.recording = Some(InputRecording {
+            seed,
+            speed: self.cycles_per_frame,
+            frames: Vec::new(),
+        });
+    }
+
+    pub fn stop_recording(&mut self) -> Option<InputRecording> {
+        self.recording.take()
+    }
+
+    /// Replays `recording` on a machine with the same ROM freshly loaded.
+    /// Keys that changed within one frame are applied lowest index first.
+    pub fn play_recording(&mut self, recording: &InputRecording) -> Result<(), Chip8Error> {
+        self.set_rng_seed(recording.seed);
+        self.set_speed(recording.speed);
+        for &mask in &recording.frames {
+            self.set_keys_held_mask(mask);
+            self.run_frame()?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn record_frame(&mut self) {
+        let mask = self.keys_held_mask();
+        if let Some(recording) = self.recording.as_mut() {
+            recording.frames.push(mask);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use crate::Machine;
+
+impl Machine {
+    /// Allows rewinding up to `capacity_frames` frames, using a snapshot
+    /// captured on every `tick_timers`. Each costs a few KiB (over 64 KiB with
+    /// XO-CHIP). A capacity of 0 disables rewinding.
+    pub fn enable_rewind(&mut self, capacity_frames: usize) {
+        self.rewind_capacity = capacity_frames;
+        if capacity_frames == 0 {
+            self.rewind_buffer.clear();
+        }
+        while self.rewind_buffer.len() > capacity_frames + 1 {
+            self.rewind_buffer.pop_front();
+        }
+    }
+
+    /// Frames currently available to `rewind`.
+    pub fn rewind_len(&self) -> usize {
+        self.rewind_buffer.len().saturating_sub(1)
+    }
+
+    /// Goes back `frames` frames, or as far as the buffer reaches, and
+    /// returns how many frames were actually rewound.
+    pub fn rewind(&mut self, frames: usize) -> usize {
+        let frames = frames.min(self.rewind_len());
+        if frames == 0 {
+            return 0;
+        }
+
+        let keep = self.rewind_buffer.len() - frames;
+        self.rewind_buffer.truncate(keep);
+        // `set_quirks` drops snapshots of another memory size, so this can't
+        // fail
+        if let Some(state) = self.rewind_buffer.back() {
+            let _ = self.restore(state.clone());
+        }
+
+        frames
+    }
+
+    pub(crate) fn checkpoint(&mut self) {
+        if self.rewind_capacity == 0 {
+            return;
+        }
+        // The newest snapshot is the current frame, so keep one extra
+        if self.rewind_buffer.len() > self.rewind_capacity {
+            self.rewind_buffer.pop_front();
+        }
+        let state = self.snapshot();
+        self.rewind_buffer.push_back(state);
+    }
+}
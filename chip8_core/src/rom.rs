@@ -0,0 +1,62 @@
+use alloc::vec::Vec;
+
+use crate::{Chip8Error, Machine, RAM_SIZE, START_ADDR, XO_RAM_SIZE};
+
+pub(crate) const FNV_OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+/// A program image checked to fit in the largest address space.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rom {
+    data: Vec<u8>,
+    hash: u64,
+}
+
+impl Rom {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Chip8Error> {
+        let max = XO_RAM_SIZE - START_ADDR as usize;
+        if data.len() > max {
+            return Err(Chip8Error::RomTooLarge {
+                size: data.len(),
+                max,
+            });
+        }
+
+        Ok(Self {
+            data: data.to_vec(),
+            hash: fnv1a(data),
+        })
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// 64-bit FNV-1a hash of the image.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Only XO-CHIP programs can use memory past 4 KiB.
+    pub fn needs_xo_chip(&self) -> bool {
+        self.data.len() > RAM_SIZE - START_ADDR as usize
+    }
+}
+
+fn fnv1a(data: &[u8]) -> u64 {
+    fnv1a_update(FNV_OFFSET, data)
+}
+
+/// Continues an FNV-1a `hash` over `data`, for hashing several buffers as one.
+pub(crate) fn fnv1a_update(hash: u64, data: &[u8]) -> u64 {
+    data.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+impl Machine {
+    /// Loads `rom` like `load`.
+    pub fn load_rom(&mut self, rom: &Rom) -> Result<(), Chip8Error> {
+        self.load(rom.data())
+    }
+}
@@ -0,0 +1,177 @@
+use alloc::vec::Vec;
+
+use crate::{
+    KeyWait, MachineState, RngState, AUDIO_PATTERN_SIZE, NUM_KEYS, NUM_REGS, NUM_RPL_FLAGS,
+    RAM_SIZE, SCREEN_BUFFER_SIZE, XO_RAM_SIZE,
+};
+
+const MAGIC: &[u8; 4] = b"C8S1";
+const PLANE_BYTES: usize = SCREEN_BUFFER_SIZE / 8;
+
+// Everything but memory and the stack: magic, PC, memory size, both planes,
+// plane mask, hires, registers, I, keys, key wait, timers, flags, halted, audio
+// pattern, RNG and stack depth
+const FIXED_LEN: usize = 4
+    + 2
+    + 4
+    + 2 * PLANE_BYTES
+    + 2
+    + NUM_REGS
+    + 2
+    + 2
+    + 2
+    + 2
+    + NUM_RPL_FLAGS
+    + 1
+    + 1
+    + AUDIO_PATTERN_SIZE
+    + 32
+    + 8
+    + 16
+    + 2;
+
+impl MachineState {
+    /// Bytes `to_bytes` writes for `ram_len` bytes of memory and
+    /// `stack_depth` return addresses, for sizing a fixed save slot.
+    pub const fn encoded_len(ram_len: usize, stack_depth: usize) -> usize {
+        FIXED_LEN + ram_len + 2 * stack_depth
+    }
+
+    /// A compact binary encoding, independent of serde. Its size only depends
+    /// on the memory size and stack depth, so slots can be allocated once.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(Self::encoded_len(self.ram.len(), self.stack.len()));
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&self.pc.to_be_bytes());
+        out.extend_from_slice(&(self.ram.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.ram);
+        for plane in [&self.screen, &self.second_plane] {
+            for pixels in plane.chunks_exact(8) {
+                out.push(
+                    pixels
+                        .iter()
+                        .fold(0, |byte, pixel| (byte << 1) | *pixel as u8),
+                );
+            }
+        }
+        out.extend_from_slice(&[self.plane_mask, self.hires as u8]);
+        out.extend_from_slice(&self.v_reg);
+        out.extend_from_slice(&self.i_reg.to_be_bytes());
+        let keys = self
+            .keys
+            .iter()
+            .enumerate()
+            .fold(0u16, |mask, (i, key)| mask | (*key as u16) << i);
+        out.extend_from_slice(&keys.to_be_bytes());
+        out.extend_from_slice(&match self.key_wait {
+            KeyWait::Idle => [0, 0],
+            KeyWait::Waiting => [1, 0],
+            KeyWait::Pressed(key) => [2, key],
+            KeyWait::Released(key) => [3, key],
+        });
+        out.extend_from_slice(&[self.dt, self.st]);
+        out.extend_from_slice(&self.rpl_flags);
+        out.push(self.halted as u8);
+        out.push(self.audio_pattern.is_some() as u8);
+        out.extend_from_slice(&self.audio_pattern.unwrap_or_default());
+        out.extend_from_slice(&self.rng.seed);
+        out.extend_from_slice(&self.rng.stream.to_be_bytes());
+        out.extend_from_slice(&self.rng.word_pos.to_be_bytes());
+        out.extend_from_slice(&(self.stack.len() as u16).to_be_bytes());
+        for addr in &self.stack {
+            out.extend_from_slice(&addr.to_be_bytes());
+        }
+
+        out
+    }
+
+    /// Decodes a state written by `to_bytes`, ignoring any padding after it.
+    /// `None` if `data` is truncated, not a state at all, or holds a memory
+    /// size other than 4 KiB or 64 KiB.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let mut reader = Reader(data);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return None;
+        }
+        let pc = reader.u16()?;
+        let ram_len = u32::from_be_bytes(reader.array()?) as usize;
+        // Anything else could only come from a corrupt or crafted state
+        if ram_len != RAM_SIZE && ram_len != XO_RAM_SIZE {
+            return None;
+        }
+        let ram = reader.take(ram_len)?.to_vec();
+        let mut planes = [[false; SCREEN_BUFFER_SIZE]; 2];
+        for plane in &mut planes {
+            let bytes = reader.take(PLANE_BYTES)?;
+            for (i, pixel) in plane.iter_mut().enumerate() {
+                *pixel = bytes[i / 8] & (0x80 >> (i % 8)) != 0;
+            }
+        }
+        let [plane_mask, hires] = reader.array()?;
+        let v_reg = reader.array()?;
+        let i_reg = reader.u16()?;
+        let keys = reader.u16()?;
+        let key_wait = match reader.array()? {
+            [0, _] => KeyWait::Idle,
+            [1, _] => KeyWait::Waiting,
+            [2, key] if (key as usize) < NUM_KEYS => KeyWait::Pressed(key),
+            [3, key] if (key as usize) < NUM_KEYS => KeyWait::Released(key),
+            _ => return None,
+        };
+        let [dt, st] = reader.array()?;
+        let rpl_flags = reader.array()?;
+        let [halted, has_pattern] = reader.array()?;
+        let pattern: [u8; AUDIO_PATTERN_SIZE] = reader.array()?;
+        let rng = RngState {
+            seed: reader.array()?,
+            stream: u64::from_be_bytes(reader.array()?),
+            word_pos: u128::from_be_bytes(reader.array()?),
+        };
+        let depth = reader.u16()? as usize;
+        let stack = (0..depth)
+            .map(|_| reader.u16())
+            .collect::<Option<Vec<_>>>()?;
+        let [screen, second_plane] = planes;
+
+        Some(Self {
+            pc,
+            ram,
+            screen,
+            second_plane,
+            plane_mask,
+            hires: hires != 0,
+            v_reg,
+            i_reg,
+            stack,
+            keys: core::array::from_fn(|i| keys & (1 << i) != 0),
+            key_wait,
+            dt,
+            st,
+            rpl_flags,
+            halted: halted != 0,
+            audio_pattern: (has_pattern != 0).then_some(pattern),
+            rng,
+        })
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.array().map(u16::from_be_bytes)
+    }
+}
@@ -0,0 +1,22 @@
+use alloc::boxed::Box;
+
+use crate::{Machine, NUM_RPL_FLAGS};
+
+/// Keeps the SUPER-CHIP `FX75`/`FX85` user flags between runs, for example in
+/// a file or browser storage, so games can save high scores and progress.
+pub trait Storage {
+    /// Flags saved by an earlier run, or `None` if nothing was saved yet.
+    fn load_flags(&mut self) -> Option<[u8; NUM_RPL_FLAGS]>;
+    /// Called every time `FX75` stores the flags.
+    fn save_flags(&mut self, flags: &[u8; NUM_RPL_FLAGS]);
+}
+
+impl Machine {
+    /// Installs `storage` and restores the flags it holds, clearing them if it
+    /// holds none, so switching ROMs doesn't leak one game's flags into
+    /// another.
+    pub fn set_storage(&mut self, mut storage: Box<dyn Storage>) {
+        self.rpl_flags = storage.load_flags().unwrap_or_default();
+        self.storage = Some(storage);
+    }
+}
@@ -0,0 +1,311 @@
+use std::{
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex, MutexGuard,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::{rom::Rom, Chip8Error, Machine, MachineState, Quirks, TIMER_HZ};
+
+// How far the loop may fall behind before it stops trying to catch up
+const MAX_LAG_FRAMES: u32 = 3;
+
+/// Settings for `EmulatorThread::spawn`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ThreadConfig {
+    /// Quirks to run with, or `None` for the defaults.
+    pub quirks: Option<Quirks>,
+    /// Instructions per frame, or `None` to keep the default speed.
+    pub speed: Option<usize>,
+    pub start_paused: bool,
+}
+
+/// The display as of the last frame the emulator thread ran.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    /// One `display_colors` index per pixel, row by row.
+    pub colors: Vec<u8>,
+    /// `display_generation` when this was captured, to skip redrawing
+    /// unchanged frames.
+    pub generation: u64,
+    pub beeping: bool,
+}
+
+impl Framebuffer {
+    fn capture(machine: &Machine) -> Self {
+        let (width, height) = machine.display_dimensions();
+        Self {
+            width,
+            height,
+            colors: machine.display_colors(),
+            generation: machine.display_generation(),
+            beeping: machine.is_beeping(),
+        }
+    }
+}
+
+enum Command {
+    Key(usize, bool),
+    KeysHeld(u16),
+    Pause,
+    Resume,
+    Restart,
+    SaveState(Sender<MachineState>),
+    LoadState(Box<MachineState>),
+    Stop,
+}
+
+struct Shared {
+    framebuffer: Framebuffer,
+    paused: bool,
+    error: Option<Chip8Error>,
+}
+
+/// Runs a `Machine` at 60 frames per second on its own thread, so a frontend
+/// can render and handle input on another.
+///
+/// Every method only queues a command or reads what the thread last
+/// published, so none of them block on emulation. The machine is built on the
+/// emulator thread, which means callbacks such as sound handlers can't be
+/// installed; read `Framebuffer::beeping` instead. Dropping the handle stops
+/// the thread.
+pub struct EmulatorThread {
+    commands: Sender<Command>,
+    shared: Arc<Mutex<Shared>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl EmulatorThread {
+    /// Loads `rom` into a new machine on a new thread and starts running it,
+    /// unless `config.start_paused` is set.
+    pub fn spawn(rom: Rom, config: ThreadConfig) -> Result<Self, Chip8Error> {
+        let (commands, receiver) = mpsc::channel();
+        let (loaded, load_result) = mpsc::channel();
+        let shared = Arc::new(Mutex::new(Shared {
+            framebuffer: Framebuffer::capture(&Machine::new()),
+            paused: config.start_paused,
+            error: None,
+        }));
+
+        let thread_shared = Arc::clone(&shared);
+        let handle = std::thread::spawn(move || {
+            let machine = match build_machine(&rom, &config) {
+                Ok(machine) => machine,
+                Err(err) => {
+                    let _ = loaded.send(Err(err));
+                    return;
+                }
+            };
+            lock(&thread_shared).framebuffer = Framebuffer::capture(&machine);
+            let _ = loaded.send(Ok(()));
+
+            Worker {
+                machine,
+                rom,
+                commands: receiver,
+                shared: thread_shared,
+            }
+            .run();
+        });
+
+        // A panic while loading drops the sender without a result
+        match load_result.recv() {
+            Ok(Ok(())) => Ok(Self {
+                commands,
+                shared,
+                handle: Some(handle),
+            }),
+            Ok(Err(err)) => Err(err),
+            Err(_) => {
+                let _ = handle.join();
+                Err(Chip8Error::ThreadPanicked)
+            }
+        }
+    }
+
+    pub fn keypress(&self, key: usize, pressed: bool) {
+        self.send(Command::Key(key, pressed));
+    }
+
+    /// Holds exactly the keys in `mask`, like `Machine::set_keys_held_mask`.
+    pub fn set_keys_held_mask(&self, mask: u16) {
+        self.send(Command::KeysHeld(mask));
+    }
+
+    pub fn pause(&self) {
+        self.send(Command::Pause);
+    }
+
+    /// Continues after `pause` or an error, clearing the error.
+    pub fn resume(&self) {
+        self.send(Command::Resume);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        lock(&self.shared).paused
+    }
+
+    /// Resets the machine and reloads the ROM.
+    pub fn restart(&self) {
+        self.send(Command::Restart);
+    }
+
+    /// The machine state between two frames, or `None` if the thread has
+    /// stopped. Waits for the current frame to finish.
+    pub fn save_state(&self) -> Option<MachineState> {
+        let (reply, state) = mpsc::channel();
+        self.send(Command::SaveState(reply));
+        state.recv().ok()
+    }
+
+    /// Restores `state`, or pauses with its error if it doesn't fit, as
+    /// `Machine::load_state` explains.
+    pub fn load_state(&self, state: MachineState) {
+        self.send(Command::LoadState(Box::new(state)));
+    }
+
+    /// A copy of the display after the most recent frame.
+    pub fn framebuffer(&self) -> Framebuffer {
+        lock(&self.shared).framebuffer.clone()
+    }
+
+    /// The error that paused the machine, if any, until `resume`, `restart`
+    /// or `load_state`. Only `resume` runs the machine again.
+    pub fn error(&self) -> Option<Chip8Error> {
+        lock(&self.shared).error
+    }
+
+    /// Stops the thread and waits for it to exit.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn send(&self, command: Command) {
+        // Only fails once the thread is gone, when there is nothing to control
+        let _ = self.commands.send(command);
+    }
+
+    fn shutdown(&mut self) {
+        self.send(Command::Stop);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for EmulatorThread {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn build_machine(rom: &Rom, config: &ThreadConfig) -> Result<Machine, Chip8Error> {
+    let mut machine = Machine::new_with_quirks(config.quirks.unwrap_or_default());
+    machine.load_rom(rom)?;
+    if let Some(speed) = config.speed {
+        machine.set_speed(speed);
+    }
+
+    Ok(machine)
+}
+
+// A panicking frontend thread can't leave `Shared` half-updated, so poisoning
+// is safe to ignore
+fn lock(shared: &Mutex<Shared>) -> MutexGuard<'_, Shared> {
+    shared
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+struct Worker {
+    machine: Machine,
+    rom: Rom,
+    commands: Receiver<Command>,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Worker {
+    fn run(mut self) {
+        let frame = Duration::from_secs(1) / TIMER_HZ;
+        let mut deadline = Instant::now();
+        loop {
+            let paused = lock(&self.shared).paused;
+            let command = if paused {
+                self.commands
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                self.commands
+                    .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            };
+            match command {
+                Ok(Command::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+                Ok(command) => {
+                    if self.handle(command) {
+                        deadline = Instant::now();
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+
+            let result = self.machine.run_frame();
+            let mut shared = lock(&self.shared);
+            shared.framebuffer = Framebuffer::capture(&self.machine);
+            if let Err(err) = result {
+                shared.error = Some(err);
+                shared.paused = true;
+            }
+            drop(shared);
+
+            deadline += frame;
+            let now = Instant::now();
+            if now > deadline + frame * MAX_LAG_FRAMES {
+                deadline = now;
+            }
+        }
+    }
+
+    /// Applies `command`, returning true if the frame clock should restart.
+    fn handle(&mut self, command: Command) -> bool {
+        match command {
+            Command::Key(key, pressed) => self.machine.keypress(key, pressed),
+            Command::KeysHeld(mask) => self.machine.set_keys_held_mask(mask),
+            Command::Pause => lock(&self.shared).paused = true,
+            Command::Resume => {
+                let mut shared = lock(&self.shared);
+                shared.paused = false;
+                shared.error = None;
+                return true;
+            }
+            Command::Restart => {
+                self.machine.reset();
+                let result = self.machine.load(self.rom.data());
+                self.publish(result.err());
+            }
+            Command::SaveState(reply) => {
+                let _ = reply.send(self.machine.save_state());
+            }
+            Command::LoadState(state) => {
+                let result = self.machine.load_state(&state);
+                self.publish(result.err());
+            }
+            Command::Stop => {}
+        }
+
+        false
+    }
+
+    fn publish(&self, error: Option<Chip8Error>) {
+        let mut shared = lock(&self.shared);
+        shared.framebuffer = Framebuffer::capture(&self.machine);
+        shared.error = error;
+        if error.is_some() {
+            shared.paused = true;
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use core::time::Duration;
+
+use crate::{opcode::Opcode, Chip8Error, Machine};
+
+/// Machine cycles the VIP interpreter has per 60 Hz frame once the display
+/// interrupt has taken its share.
+pub(crate) const VIP_CYCLES_PER_FRAME: u32 = 1832;
+
+// Fetch and decode overhead shared by every instruction
+const VIP_DISPATCH_CYCLES: u32 = 68;
+
+/// Approximate machine cycles the COSMAC VIP interpreter spends on `op`.
+/// Register-dependent costs (BCD, load/store, sprite size) are folded into
+/// typical values.
+pub(crate) fn vip_cycles(op: Opcode) -> u32 {
+    let cost = match op {
+        Opcode::ClearScreen => 3078,
+        Opcode::Return => 10,
+        Opcode::Jump(_) => 12,
+        Opcode::Call(_) => 26,
+        Opcode::SkipEqImm { .. } | Opcode::SkipNeImm { .. } => 10,
+        Opcode::SkipEqReg { .. } | Opcode::SkipNeReg { .. } => 14,
+        Opcode::LoadImm { .. } => 6,
+        Opcode::AddImm { .. } => 10,
+        Opcode::LoadReg { .. }
+        | Opcode::Or { .. }
+        | Opcode::And { .. }
+        | Opcode::Xor { .. }
+        | Opcode::AddReg { .. }
+        | Opcode::SubReg { .. }
+        | Opcode::ShiftRight { .. }
+        | Opcode::SubNReg { .. }
+        | Opcode::ShiftLeft { .. } => 44,
+        Opcode::LoadIndex(_) => 12,
+        Opcode::JumpOffset { .. } => 22,
+        Opcode::Random { .. } => 36,
+        Opcode::Draw { n, .. } => 22 + 46 * n as u32,
+        Opcode::SkipKeyPressed { .. } | Opcode::SkipKeyNotPressed { .. } => 14,
+        Opcode::LoadDelay { .. }
+        | Opcode::WaitKey { .. }
+        | Opcode::SetDelay { .. }
+        | Opcode::SetSound { .. } => 10,
+        Opcode::AddIndex { .. } | Opcode::LoadFont { .. } => 16,
+        Opcode::StoreBcd { .. } => 84,
+        Opcode::StoreRegs { x } | Opcode::LoadRegs { x } => 14 + 14 * (x as u32 + 1),
+        // Not part of the VIP's instruction set
+        _ => 0,
+    };
+
+    VIP_DISPATCH_CYCLES + cost
+}
+
+// Frame length in units of 1/60 ns, so that 60 frames are exactly a second
+const FRAME_TICKS: u128 = 1_000_000_000;
+
+/// Progress through the current frame for `advance` and `advance_cycles`.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Clock {
+    // Time into the frame in 1/60 ns, only used by `advance`
+    phase: u128,
+    // Frame budget spent so far, as counted by `frame_budget`
+    spent: usize,
+    // The frame ended early, on a key wait, `00FD` or a waiting draw
+    blocked: bool,
+    started: bool,
+}
+
+impl Machine {
+    /// What `run_frame` may spend per frame: instructions, or VIP machine
+    /// cycles with the `vip_timing` quirk.
+    pub(crate) fn frame_budget(&self) -> usize {
+        if self.quirks.vip_timing {
+            VIP_CYCLES_PER_FRAME as usize
+        } else {
+            self.cycles_per_frame
+        }
+    }
+
+    /// Executes one instruction of a frame, returning what it cost out of
+    /// `frame_budget` and whether the rest of the frame should be skipped.
+    pub(crate) fn frame_step(&mut self) -> Result<(usize, bool), Chip8Error> {
+        let info = self.step()?;
+        let cost = if self.quirks.vip_timing {
+            vip_cycles(info.instruction()) as usize
+        } else {
+            1
+        };
+        let display_wait = self.quirks.display_wait || self.quirks.vip_timing;
+        let drew = info.opcode & 0xF000 == 0xD000;
+        let blocks = self.is_waiting_for_key() || self.halted || (display_wait && drew);
+
+        Ok((cost, blocks))
+    }
+
+    /// Runs the host-supplied `elapsed` time: instructions spread evenly over
+    /// each 60 Hz frame at `speed()` per frame (or the VIP cycle budget), with
+    /// the timers ticking at every frame boundary crossed. Leftover time
+    /// carries over to the next call, so calling this from any loop or async
+    /// task keeps accurate time without drift.
+    pub fn advance(&mut self, elapsed: Duration) -> Result<(), Chip8Error> {
+        let mut ticks = elapsed.as_nanos() * 60;
+        loop {
+            let step = ticks.min(FRAME_TICKS - self.clock.phase);
+            self.clock.phase += step;
+            ticks -= step;
+
+            let budget = self.frame_budget();
+            let due = (budget as u128 * self.clock.phase / FRAME_TICKS) as usize;
+            self.run_clocked(due)?;
+            if self.clock.phase == FRAME_TICKS {
+                self.finish_clocked_frame();
+            }
+            if ticks == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Like `advance`, but the host counts in `frame_budget` units instead of
+    /// time: `n` instructions, or `n` VIP machine cycles with `vip_timing`.
+    /// The timers tick each time a frame's worth has run. Don't mix with
+    /// `advance`, which measures the frame in time instead.
+    pub fn advance_cycles(&mut self, n: usize) -> Result<(), Chip8Error> {
+        let mut left = n;
+        while left > 0 {
+            let budget = self.frame_budget().max(1);
+            let before = self.clock.spent;
+            let target = before.saturating_add(left).min(budget);
+            self.run_clocked(target)?;
+            if self.clock.blocked {
+                // The rest of the frame passes idle
+                self.clock.spent = self.clock.spent.max(target);
+            }
+            left -= (self.clock.spent - before).min(left);
+            if self.clock.spent >= budget {
+                self.finish_clocked_frame();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn run_clocked(&mut self, target: usize) -> Result<(), Chip8Error> {
+        while !self.clock.blocked && self.clock.spent < target {
+            if !self.clock.started {
+                self.record_frame();
+                self.apply_pokes();
+                self.clock.started = true;
+            }
+            let (cost, blocks) = self.frame_step()?;
+            self.clock.spent += cost;
+            self.clock.blocked = blocks;
+        }
+
+        Ok(())
+    }
+
+    fn finish_clocked_frame(&mut self) {
+        self.tick_timers();
+        self.clock = Clock::default();
+    }
+}
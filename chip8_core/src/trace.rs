@@ -0,0 +1,117 @@
+use alloc::boxed::Box;
+
+use crate::{Chip8Error, Machine, NUM_REGS};
+
+/// Something observable a running program did, reported to the event hook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceEvent {
+    /// An instruction finished executing.
+    Executed {
+        pc: u16,
+        opcode: u16,
+    },
+    /// An unassigned opcode was skipped under `InvalidOpcodePolicy::Log`.
+    InvalidOpcode {
+        pc: u16,
+        opcode: u16,
+    },
+    /// An instruction read `len` bytes of data from `addr`. Instruction
+    /// fetches are not reported.
+    MemoryRead {
+        addr: u16,
+        len: usize,
+    },
+    MemoryWrite {
+        addr: u16,
+        len: usize,
+    },
+    /// `FX0A` started waiting for a key.
+    KeyWaitStarted,
+    KeyWaitFinished {
+        key: u8,
+    },
+    Draw {
+        x: u8,
+        y: u8,
+        rows: usize,
+        collision: bool,
+    },
+    /// The sound timer started (`true`) or stopped (`false`) running.
+    Sound(bool),
+}
+
+/// One executed instruction and the registers it left behind, for comparing
+/// runs step by step against a reference emulator's trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub pc: u16,
+    pub opcode: u16,
+    pub v_reg: [u8; NUM_REGS],
+    pub i_reg: u16,
+}
+
+pub type EventHook = Box<dyn FnMut(&TraceEvent)>;
+
+/// Called with the error and the machine as it was when the error happened.
+pub type FaultHook = Box<dyn FnMut(&Chip8Error, &Machine)>;
+
+impl Machine {
+    /// Registers an observer for every `TraceEvent`, for logging or profiling
+    /// frontends.
+    pub fn set_event_hook(&mut self, hook: EventHook) {
+        self.event_hook = Some(hook);
+    }
+
+    /// Registers a callback for every error an instruction fails with, from
+    /// `step`, `tick`, `run_frame` or any other way of running the machine,
+    /// before the error is returned. Useful for a crash report showing the
+    /// faulting PC and opcode.
+    pub fn on_fault(&mut self, hook: FaultHook) {
+        self.fault_hook = Some(hook);
+    }
+
+    /// Executes one instruction per record in `reference`, comparing what
+    /// ran and the registers afterwards. On the first mismatch returns its
+    /// index with the expected and the actual record. An instruction that
+    /// fails is a mismatch too, reported with the opcode at the PC it failed
+    /// at and the registers as they were.
+    pub fn run_checked(
+        &mut self,
+        reference: &[TraceRecord],
+    ) -> Result<(), (usize, TraceRecord, TraceRecord)> {
+        for (index, expected) in reference.iter().enumerate() {
+            let pc = self.pc;
+            let (pc, opcode) = match self.step() {
+                Ok(info) => (info.pc, info.opcode),
+                Err(_) => (pc, self.read16(pc).unwrap_or_default()),
+            };
+            let actual = TraceRecord {
+                pc,
+                opcode,
+                v_reg: self.v_reg,
+                i_reg: self.i_reg,
+            };
+            if actual != *expected {
+                return Err((index, *expected, actual));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn report_fault(&mut self, err: &Chip8Error) {
+        if let Some(mut hook) = self.fault_hook.take() {
+            hook(err, self);
+            self.fault_hook = Some(hook);
+        }
+    }
+
+    pub(crate) fn emit(&mut self, event: TraceEvent) {
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record(&event, self.quirks.xo_chip);
+        }
+        if let Some(hook) = self.event_hook.as_mut() {
+            hook(&event);
+        }
+    }
+}
@@ -0,0 +1,429 @@
+use chip8_core::{debugger::TickResult, Chip8Error, Machine};
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+};
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// Register numbers used by `p`/`P`, and their order in `g`/`G`: V0-VF, then
+/// I and PC as 16-bit little-endian values, then DT and ST.
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_DT: usize = 18;
+const REG_ST: usize = 19;
+const NUM_GDB_REGS: usize = 20;
+
+// The registers as `g` sends them, so gdb knows the layout without a
+// CHIP-8 architecture of its own
+fn target_xml() -> String {
+    let mut regs: Vec<_> = (0..16)
+        .map(|reg| format!("<reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\"/>", reg))
+        .collect();
+    regs.push(r#"<reg name="i" bitsize="16" type="data_ptr"/>"#.to_string());
+    regs.push(r#"<reg name="pc" bitsize="16" type="code_ptr"/>"#.to_string());
+    regs.push(r#"<reg name="dt" bitsize="8" type="uint8"/>"#.to_string());
+    regs.push(r#"<reg name="st" bitsize="8" type="uint8"/>"#.to_string());
+
+    format!(
+        concat!(
+            r#"<?xml version="1.0"?><!DOCTYPE target SYSTEM "gdb-target.dtd">"#,
+            r#"<target version="1.0"><feature name="org.chip8.core">{}</feature></target>"#
+        ),
+        regs.concat()
+    )
+}
+
+fn signal(err: Chip8Error) -> u8 {
+    match err {
+        Chip8Error::InvalidOpcode(_) => SIGILL,
+        _ => SIGSEGV,
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [_, _] => u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+// Answers `qXfer:features:read:<annex>:<offset>,<length>` with one chunk
+// of the target description, `m` while more follows and `l` for the last
+fn read_features(args: &str) -> String {
+    let Some(("target.xml", range)) = args.split_once(':') else {
+        return "E00".to_string();
+    };
+    let Some((offset, len)) = range
+        .split_once(',')
+        .and_then(|(offset, len)| Some((parse_hex(offset)?, parse_hex(len)?)))
+    else {
+        return "E00".to_string();
+    };
+
+    let xml = target_xml();
+    let start = offset.min(xml.len());
+    let end = start.saturating_add(len).min(xml.len());
+    let kind = if end < xml.len() { 'm' } else { 'l' };
+    format!("{}{}", kind, &xml[start..end])
+}
+
+/// Serves one gdb remote serial protocol session for `machine`.
+pub struct GdbStub<'a> {
+    stream: TcpStream,
+    machine: &'a mut Machine,
+}
+
+impl<'a> GdbStub<'a> {
+    pub fn new(stream: TcpStream, machine: &'a mut Machine) -> Self {
+        Self { stream, machine }
+    }
+
+    /// Handles packets until the debugger detaches, kills the session or
+    /// disconnects.
+    pub fn serve(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            let reply = match packet.as_bytes().first() {
+                Some(b'D') => {
+                    self.send("OK")?;
+                    return Ok(());
+                }
+                Some(b'k') => return Ok(()),
+                _ => self.handle(&packet)?,
+            };
+            self.send(&reply)?;
+        }
+
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &str) -> io::Result<String> {
+        // Malformed input from the network must not take the stub down, and
+        // gdb treats an empty reply as "unsupported"
+        let mut chars = packet.chars();
+        let Some(command) = chars.next() else {
+            return Ok(String::new());
+        };
+        let args = chars.as_str();
+        let reply = match command {
+            '?' => format!("S{:02x}", SIGTRAP),
+            'g' => hex(&self.registers()),
+            'G' => match unhex(args) {
+                Some(bytes) if bytes.len() == self.registers().len() => {
+                    self.set_registers(&bytes);
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            'p' => match parse_hex(args).and_then(|reg| self.register(reg)) {
+                Some(bytes) => hex(&bytes),
+                None => "E01".to_string(),
+            },
+            'P' => {
+                let write = args
+                    .split_once('=')
+                    .and_then(|(reg, value)| Some((parse_hex(reg)?, unhex(value)?)));
+                match write {
+                    Some((reg, bytes)) if self.set_register(reg, &bytes) => "OK".to_string(),
+                    _ => "E01".to_string(),
+                }
+            }
+            'm' => self.read_memory(args).unwrap_or_else(|| "E01".to_string()),
+            'M' => self.write_memory(args).unwrap_or_else(|| "E01".to_string()),
+            'Z' | 'z' => self.breakpoint(command == 'Z', args),
+            's' => {
+                let sig = match self.machine.step() {
+                    Ok(_) => SIGTRAP,
+                    Err(err) => signal(err),
+                };
+                format!("S{:02x}", sig)
+            }
+            'c' => format!("S{:02x}", self.resume()?),
+            'H' => "OK".to_string(),
+            'q' if args.starts_with("Supported") => {
+                "PacketSize=4000;qXfer:features:read+".to_string()
+            }
+            'q' if args.starts_with("Xfer:features:read:") => {
+                read_features(&args["Xfer:features:read:".len()..])
+            }
+            'q' if args == "Attached" => "1".to_string(),
+            _ => String::new(),
+        };
+
+        Ok(reply)
+    }
+
+    fn register(&self, reg: usize) -> Option<Vec<u8>> {
+        let m = &self.machine;
+        let bytes = match reg {
+            0..=15 => vec![m.v(reg)],
+            REG_I => m.index_register().to_le_bytes().to_vec(),
+            REG_PC => m.program_counter().to_le_bytes().to_vec(),
+            REG_DT => vec![m.delay_timer()],
+            REG_ST => vec![m.sound_timer()],
+            _ => return None,
+        };
+
+        Some(bytes)
+    }
+
+    fn set_register(&mut self, reg: usize, bytes: &[u8]) -> bool {
+        let m = &mut self.machine;
+        match (reg, bytes) {
+            (0..=15, [value]) => m.set_v(reg, *value),
+            (REG_I, [low, high]) => m.set_index_register(u16::from_le_bytes([*low, *high])),
+            (REG_PC, [low, high]) => m.set_program_counter(u16::from_le_bytes([*low, *high])),
+            (REG_DT, [value]) => m.set_delay_timer(*value),
+            (REG_ST, [value]) => m.set_sound_timer(*value),
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        (0..NUM_GDB_REGS)
+            .flat_map(|reg| self.register(reg).unwrap_or_default())
+            .collect()
+    }
+
+    fn set_registers(&mut self, mut bytes: &[u8]) {
+        for reg in 0..NUM_GDB_REGS {
+            let len = self.register(reg).map_or(0, |value| value.len());
+            let (value, rest) = bytes.split_at(len);
+            self.set_register(reg, value);
+            bytes = rest;
+        }
+    }
+
+    fn memory_range(&self, args: &str) -> Option<(usize, usize)> {
+        let (addr, len) = args.split_once(',')?;
+        let (addr, len) = (parse_hex(addr)?, parse_hex(len)?);
+        (addr.checked_add(len)? <= self.machine.ram().len()).then_some((addr, len))
+    }
+
+    fn read_memory(&self, args: &str) -> Option<String> {
+        let (addr, len) = self.memory_range(args)?;
+        Some(hex(&self.machine.ram()[addr..addr + len]))
+    }
+
+    fn write_memory(&mut self, args: &str) -> Option<String> {
+        let (range, data) = args.split_once(':')?;
+        let (addr, len) = self.memory_range(range)?;
+        let bytes = unhex(data).filter(|bytes| bytes.len() == len)?;
+        self.machine.ram_mut()[addr..addr + len].copy_from_slice(&bytes);
+        Some("OK".to_string())
+    }
+
+    fn breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let (Some("0"), Some(addr)) = (fields.next(), fields.next().and_then(parse_hex)) else {
+            // Only software breakpoints are supported
+            return String::new();
+        };
+        if insert {
+            self.machine.add_breakpoint(addr as u16);
+        } else {
+            self.machine.remove_breakpoint(addr as u16);
+        }
+
+        "OK".to_string()
+    }
+
+    /// Runs until a breakpoint, an error or a ^C from the debugger, and returns
+    /// the signal to report. Timers tick once per `speed()` instructions so
+    /// they keep roughly their usual pace.
+    fn resume(&mut self) -> io::Result<u8> {
+        // Step off the breakpoint the PC may be sitting on before checking again
+        let mut result = self.machine.step().map(TickResult::from);
+        let mut cycles = 1;
+        loop {
+            match result {
+                Ok(TickResult::Stepped(_)) => {}
+                Ok(TickResult::BreakpointHit(_) | TickResult::WatchpointHit { .. }) => {
+                    return Ok(SIGTRAP)
+                }
+                Err(err) => return Ok(signal(err)),
+            }
+            if cycles % self.machine.speed().max(1) == 0 {
+                self.machine.tick_timers();
+                if self.interrupted()? {
+                    return Ok(SIGINT);
+                }
+            }
+            result = self.machine.debug_tick();
+            cycles += 1;
+        }
+    }
+
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut byte = [0];
+        let result = self.stream.read(&mut byte);
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Ok(1) => Ok(byte[0] == 0x03),
+            Ok(_) => Err(io::ErrorKind::UnexpectedEof.into()),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    /// Next packet body, acknowledging it, or `None` once the connection closes.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // Skip acks and stray interrupts until a packet starts
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(_) => continue,
+            }
+
+            let mut body = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => body.push(byte),
+                }
+            }
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum)?;
+
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|text| u8::from_str_radix(text, 16).ok());
+            let actual = body.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+            if expected != Some(actual) {
+                self.stream.write_all(b"-")?;
+                continue;
+            }
+            self.stream.write_all(b"+")?;
+
+            return Ok(Some(String::from_utf8_lossy(&body).into_owned()));
+        }
+    }
+
+    fn send(&mut self, body: &str) -> io::Result<()> {
+        let checksum = body.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", body, checksum)?;
+        self.stream.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn connect(machine: &mut Machine) -> (GdbStub<'_>, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (GdbStub::new(server, machine), client)
+    }
+
+    fn packet(body: &str) -> String {
+        let checksum = body.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        format!("${}#{:02x}", body, checksum)
+    }
+
+    fn read_reply(client: &mut TcpStream, len: usize) -> String {
+        let mut reply = vec![0; len];
+        client.read_exact(&mut reply).unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+
+    #[test]
+    fn packets_are_checked_and_acknowledged() {
+        let mut machine = Machine::new();
+        let (mut stub, mut client) = connect(&mut machine);
+        write!(client, "+$g#00{}", packet("m200,2")).unwrap();
+        assert_eq!(stub.read_packet().unwrap().as_deref(), Some("m200,2"));
+        assert_eq!(read_reply(&mut client, 2), "-+");
+
+        stub.send("OK").unwrap();
+        assert_eq!(read_reply(&mut client, 6), "$OK#9a");
+
+        drop(client);
+        assert_eq!(stub.read_packet().unwrap(), None);
+    }
+
+    #[test]
+    fn registers_and_memory_read_back() {
+        let mut machine = Machine::new();
+        machine.load(&[0x6A, 0x02]).unwrap();
+        machine.set_v(0, 0x12);
+        machine.set_index_register(0x345);
+        let (mut stub, _client) = connect(&mut machine);
+
+        let registers = stub.handle("g").unwrap();
+        assert_eq!(registers.len(), 2 * 22);
+        assert!(registers.starts_with("1200"));
+        assert!(registers.ends_with("450300020000"));
+        assert_eq!(stub.handle("p11").unwrap(), "0002");
+        assert_eq!(stub.handle("m200,2").unwrap(), "6a02");
+        assert_eq!(stub.handle("mfff,2").unwrap(), "E01");
+        assert_eq!(stub.handle("mzz").unwrap(), "E01");
+        assert_eq!(stub.handle("").unwrap(), "");
+    }
+
+    #[test]
+    fn software_breakpoints_are_set_and_cleared() {
+        let mut machine = Machine::new();
+        let (mut stub, _client) = connect(&mut machine);
+        assert_eq!(stub.handle("Z0,202,2").unwrap(), "OK");
+        assert_eq!(stub.machine.breakpoints(), [0x202]);
+        assert_eq!(stub.handle("Z1,204,2").unwrap(), "");
+        assert_eq!(stub.handle("z0,202,2").unwrap(), "OK");
+        assert!(stub.machine.breakpoints().is_empty());
+    }
+
+    #[test]
+    fn target_description_is_served_in_chunks() {
+        let mut machine = Machine::new();
+        let (mut stub, _client) = connect(&mut machine);
+        assert!(stub
+            .handle("qSupported:xmlRegisters=i386")
+            .unwrap()
+            .contains("qXfer:features:read+"));
+
+        let xml = target_xml();
+        let first = stub.handle("qXfer:features:read:target.xml:0,10").unwrap();
+        assert_eq!(first, format!("m{}", &xml[..0x10]));
+        let rest = stub
+            .handle(&format!(
+                "qXfer:features:read:target.xml:10,{:x}",
+                xml.len()
+            ))
+            .unwrap();
+        assert_eq!(rest, format!("l{}", &xml[0x10..]));
+        assert_eq!(xml.matches("<reg ").count(), NUM_GDB_REGS);
+        assert_eq!(
+            stub.handle("qXfer:features:read:other.xml:0,10").unwrap(),
+            "E00"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use chip8_core::{CompatibilityProfile, Machine, Quirks};
+use chip8_gdb::GdbStub;
+use std::{env, net::TcpListener, process::exit};
+
+const USAGE: &str = "Usage: chip8_gdb [options] path/to/game
+
+Waits for a debugger on a gdb remote serial protocol port and serves one
+session. Registers are numbered V0-VF (0-15), I (16), PC (17), DT (18) and
+ST (19); I and PC are 16-bit little-endian.
+
+Options:
+    --port N         TCP port to listen on (default 1234)
+    --speed N        instructions per timer tick while continuing (default 10)
+    --profile NAME   vip, schip or xochip (default: the core's defaults)";
+
+struct Options {
+    rom: String,
+    port: u16,
+    speed: usize,
+    quirks: Quirks,
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    exit(2);
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: Option<String>) -> T {
+    value
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(|| fail(&format!("{} expects a number", flag)))
+}
+
+fn parse_args() -> Options {
+    let mut options = Options {
+        rom: String::new(),
+        port: 1234,
+        speed: 10,
+        quirks: Quirks::default(),
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => options.port = number(&arg, args.next()),
+            "--speed" => options.speed = number(&arg, args.next()),
+            "--profile" => {
+                options.quirks = match args.next().as_deref() {
+                    Some("vip") => CompatibilityProfile::CosmacVip.into(),
+                    Some("schip") => CompatibilityProfile::SuperChip.into(),
+                    Some("xochip") => CompatibilityProfile::XoChip.into(),
+                    _ => fail("--profile expects vip, schip or xochip"),
+                }
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            _ if arg.starts_with("--") => fail(&format!("unknown option {}", arg)),
+            _ if options.rom.is_empty() => options.rom = arg,
+            _ => fail("only one ROM can be given"),
+        }
+    }
+
+    if options.rom.is_empty() {
+        fail("no ROM given");
+    }
+
+    options
+}
+
+fn main() {
+    let options = parse_args();
+
+    let mut chip8 = Machine::new_with_quirks(options.quirks);
+    chip8.set_speed(options.speed);
+    if let Err(err) = chip8.load_from_path(&options.rom) {
+        eprintln!("Unable to load ROM: {}", err);
+        exit(1);
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", options.port)).unwrap_or_else(|err| {
+        eprintln!("Unable to listen on port {}: {}", options.port, err);
+        exit(1);
+    });
+    println!("Waiting for a debugger on 127.0.0.1:{}", options.port);
+
+    let (stream, addr) = listener.accept().unwrap_or_else(|err| {
+        eprintln!("Unable to accept a connection: {}", err);
+        exit(1);
+    });
+    println!("Debugger connected from {}", addr);
+
+    if let Err(err) = GdbStub::new(stream, &mut chip8).serve() {
+        eprintln!("Connection lost: {}", err);
+        exit(1);
+    }
+}
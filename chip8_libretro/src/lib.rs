@@ -0,0 +1,507 @@
+//! A libretro core, so RetroArch and other libretro frontends can run CHIP-8,
+//! SUPER-CHIP and XO-CHIP ROMs.
+//!
+//! Core options pick the compatibility profile and the speed. "auto" uses
+//! XO-CHIP for ROMs too large for 4 KiB and the core's defaults otherwise.
+
+mod ffi;
+
+use chip8_core::{rom::Rom, CompatibilityProfile, Machine, MachineState, Quirks};
+use ffi::*;
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_uint, c_void, CStr},
+    ptr, slice,
+};
+
+const NUM_KEYS: usize = 16;
+
+const FPS: f64 = 60.0;
+const SAMPLE_RATE: u32 = 44100;
+const SAMPLES_PER_FRAME: usize = SAMPLE_RATE as usize / FPS as usize;
+
+// XRGB8888 colors for the four XO-CHIP plane combinations
+const PALETTE: [u32; 4] = [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555];
+
+// Return addresses a save state has room for. Every profile the core offers
+// limits calls to this depth, so states always fit.
+const STATE_STACK_DEPTH: usize = 16;
+
+// Directions on 2/4/6/8 and fire on 5, as most ROMs use
+const JOYPAD_KEYS: [(c_uint, usize); 10] = [
+    (RETRO_DEVICE_ID_JOYPAD_UP, 0x2),
+    (RETRO_DEVICE_ID_JOYPAD_DOWN, 0x8),
+    (RETRO_DEVICE_ID_JOYPAD_LEFT, 0x4),
+    (RETRO_DEVICE_ID_JOYPAD_RIGHT, 0x6),
+    (RETRO_DEVICE_ID_JOYPAD_A, 0x5),
+    (RETRO_DEVICE_ID_JOYPAD_B, 0x0),
+    (RETRO_DEVICE_ID_JOYPAD_X, 0x1),
+    (RETRO_DEVICE_ID_JOYPAD_Y, 0x3),
+    (RETRO_DEVICE_ID_JOYPAD_START, 0xF),
+    (RETRO_DEVICE_ID_JOYPAD_SELECT, 0xE),
+];
+
+// The usual 1234/QWER/ASDF/ZXCV layout; RETROK codes for digits and letters
+// are their lowercase ASCII values
+const KEYBOARD_KEYS: [(u8, usize); NUM_KEYS] = [
+    (b'1', 0x1),
+    (b'2', 0x2),
+    (b'3', 0x3),
+    (b'4', 0xC),
+    (b'q', 0x4),
+    (b'w', 0x5),
+    (b'e', 0x6),
+    (b'r', 0xD),
+    (b'a', 0x7),
+    (b's', 0x8),
+    (b'd', 0x9),
+    (b'f', 0xE),
+    (b'z', 0xA),
+    (b'x', 0x0),
+    (b'c', 0xB),
+    (b'v', 0xF),
+];
+
+const PROFILE_OPTION: &CStr = c"chip8_profile";
+const SPEED_OPTION: &CStr = c"chip8_speed";
+
+#[derive(Default)]
+struct Callbacks {
+    environment: Option<RetroEnvironment>,
+    video: Option<RetroVideoRefresh>,
+    audio: Option<RetroAudioSampleBatch>,
+    input_poll: Option<RetroInputPoll>,
+    input_state: Option<RetroInputState>,
+}
+
+struct Core {
+    machine: Machine,
+    rom: Rom,
+    profile: Option<CompatibilityProfile>,
+    speed: Option<usize>,
+    // `Machine`'s own speed, used while the speed option is "auto"
+    default_speed: usize,
+    // Set once the program hits an error; it stays frozen until a reset
+    crashed: bool,
+    frame: Vec<u32>,
+    audio: Vec<f32>,
+    samples: Vec<i16>,
+}
+
+impl Core {
+    fn new(rom: Rom) -> Self {
+        // Growing memory to XO-CHIP's 64 KiB once up front means switching
+        // profiles later never moves the SYSTEM_RAM buffer the frontend holds
+        let mut machine = Machine::new_with_quirks(CompatibilityProfile::XoChip.into());
+        machine.set_quirks(Quirks::default());
+        Self {
+            default_speed: machine.speed(),
+            machine,
+            rom,
+            profile: None,
+            speed: None,
+            crashed: false,
+            frame: Vec::new(),
+            audio: vec![0.0; SAMPLES_PER_FRAME],
+            samples: vec![0; SAMPLES_PER_FRAME * 2],
+        }
+    }
+
+    fn quirks(&self) -> Quirks {
+        match self.profile {
+            Some(profile) => profile.into(),
+            None if self.rom.needs_xo_chip() => CompatibilityProfile::XoChip.into(),
+            None => Quirks::default(),
+        }
+    }
+
+    /// Restarts the ROM with the current options.
+    fn start(&mut self) -> bool {
+        self.machine.set_quirks(self.quirks());
+        self.machine.reset();
+        self.crashed = false;
+        if self.machine.load_rom(&self.rom).is_err() {
+            return false;
+        }
+        self.apply_speed();
+
+        true
+    }
+
+    fn apply_speed(&mut self) {
+        self.machine
+            .set_speed(self.speed.unwrap_or(self.default_speed));
+    }
+
+    fn read_options(&mut self, environment: RetroEnvironment) {
+        self.profile = match variable(environment, PROFILE_OPTION).as_deref() {
+            Some("vip") => Some(CompatibilityProfile::CosmacVip),
+            Some("schip") => Some(CompatibilityProfile::SuperChip),
+            Some("xochip") => Some(CompatibilityProfile::XoChip),
+            _ => None,
+        };
+        self.speed = variable(environment, SPEED_OPTION).and_then(|speed| speed.parse().ok());
+    }
+
+    fn poll_input(&mut self, input_state: RetroInputState) {
+        let mut pressed = [false; NUM_KEYS];
+        for (id, key) in JOYPAD_KEYS {
+            pressed[key] |= unsafe { input_state(0, RETRO_DEVICE_JOYPAD, 0, id) } != 0;
+        }
+        for (code, key) in KEYBOARD_KEYS {
+            pressed[key] |=
+                unsafe { input_state(0, RETRO_DEVICE_KEYBOARD, 0, code as c_uint) } != 0;
+        }
+
+        for (key, pressed) in pressed.into_iter().enumerate() {
+            if self.machine.keys()[key] != pressed {
+                self.machine.keypress(key, pressed);
+            }
+        }
+    }
+
+    fn render(&mut self) -> (usize, usize) {
+        self.frame.clear();
+        self.frame.extend(
+            self.machine
+                .display_colors()
+                .into_iter()
+                .map(|color| PALETTE[color as usize]),
+        );
+
+        self.machine.display_dimensions()
+    }
+
+    fn mix_audio(&mut self) -> &[i16] {
+        self.machine.fill_audio(&mut self.audio, SAMPLE_RATE);
+        for (pair, sample) in self.samples.chunks_mut(2).zip(&self.audio) {
+            pair.fill((sample * i16::MAX as f32) as i16);
+        }
+
+        &self.samples
+    }
+}
+
+thread_local! {
+    static CALLBACKS: RefCell<Callbacks> = RefCell::default();
+    static CORE: RefCell<Option<Core>> = const { RefCell::new(None) };
+}
+
+fn variable(environment: RetroEnvironment, key: &CStr) -> Option<String> {
+    let mut var = RetroVariable {
+        key: key.as_ptr(),
+        value: ptr::null(),
+    };
+    let found = unsafe {
+        environment(
+            RETRO_ENVIRONMENT_GET_VARIABLE,
+            &mut var as *mut _ as *mut c_void,
+        )
+    };
+    if !found || var.value.is_null() {
+        return None;
+    }
+
+    unsafe { CStr::from_ptr(var.value) }
+        .to_str()
+        .ok()
+        .map(str::to_string)
+}
+
+fn with_core<T>(default: T, f: impl FnOnce(&mut Core) -> T) -> T {
+    CORE.with_borrow_mut(|core| core.as_mut().map_or(default, f))
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(environment: RetroEnvironment) {
+    CALLBACKS.with_borrow_mut(|callbacks| callbacks.environment = Some(environment));
+
+    let mut variables = [
+        RetroVariable {
+            key: PROFILE_OPTION.as_ptr(),
+            value: c"Compatibility profile; auto|vip|schip|xochip".as_ptr(),
+        },
+        RetroVariable {
+            key: SPEED_OPTION.as_ptr(),
+            value: c"Instructions per frame; auto|5|10|15|20|30|50|100|200|500|1000".as_ptr(),
+        },
+        RetroVariable {
+            key: ptr::null(),
+            value: ptr::null(),
+        },
+    ];
+    let mut no_game = false;
+    unsafe {
+        environment(
+            RETRO_ENVIRONMENT_SET_VARIABLES,
+            variables.as_mut_ptr() as *mut c_void,
+        );
+        environment(
+            RETRO_ENVIRONMENT_SET_SUPPORT_NO_GAME,
+            &mut no_game as *mut _ as *mut c_void,
+        );
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(video: RetroVideoRefresh) {
+    CALLBACKS.with_borrow_mut(|callbacks| callbacks.video = Some(video));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_audio: RetroAudioSample) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(audio: RetroAudioSampleBatch) {
+    CALLBACKS.with_borrow_mut(|callbacks| callbacks.audio = Some(audio));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(input_poll: RetroInputPoll) {
+    CALLBACKS.with_borrow_mut(|callbacks| callbacks.input_poll = Some(input_poll));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(input_state: RetroInputState) {
+    CALLBACKS.with_borrow_mut(|callbacks| callbacks.input_state = Some(input_state));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    CORE.with_borrow_mut(|core| *core = None);
+}
+
+/// # Safety
+///
+/// `info` must point to a writable `retro_system_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut RetroSystemInfo) {
+    *info = RetroSystemInfo {
+        library_name: c"chip8".as_ptr(),
+        library_version: c"0.1.0".as_ptr(),
+        valid_extensions: c"ch8|sc8|xo8|c8".as_ptr(),
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+/// # Safety
+///
+/// `info` must point to a writable `retro_system_av_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut RetroSystemAvInfo) {
+    *info = RetroSystemAvInfo {
+        geometry: RetroGameGeometry {
+            base_width: chip8_core::SCREEN_WIDTH as c_uint,
+            base_height: chip8_core::SCREEN_HEIGHT as c_uint,
+            max_width: chip8_core::HIRES_SCREEN_WIDTH as c_uint,
+            max_height: chip8_core::HIRES_SCREEN_HEIGHT as c_uint,
+            aspect_ratio: 2.0,
+        },
+        timing: RetroSystemTiming {
+            fps: FPS,
+            sample_rate: SAMPLE_RATE as f64,
+        },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    with_core((), |core| {
+        core.start();
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let (environment, video, audio, input_poll, input_state) = CALLBACKS.with_borrow(|callbacks| {
+        (
+            callbacks.environment,
+            callbacks.video,
+            callbacks.audio,
+            callbacks.input_poll,
+            callbacks.input_state,
+        )
+    });
+
+    with_core((), |core| {
+        if let Some(environment) = environment {
+            let mut updated = false;
+            unsafe {
+                environment(
+                    RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE,
+                    &mut updated as *mut _ as *mut c_void,
+                );
+            }
+            if updated {
+                // Quirks can change the memory size, so only a new profile
+                // needs a restart
+                let quirks = core.quirks();
+                core.read_options(environment);
+                if core.quirks() != quirks {
+                    core.start();
+                } else {
+                    core.apply_speed();
+                }
+            }
+        }
+
+        if let (Some(input_poll), Some(input_state)) = (input_poll, input_state) {
+            unsafe { input_poll() };
+            core.poll_input(input_state);
+        }
+
+        if !core.crashed && core.machine.run_frame().is_err() {
+            core.crashed = true;
+        }
+
+        if let Some(video) = video {
+            let (width, height) = core.render();
+            unsafe {
+                video(
+                    core.frame.as_ptr() as *const c_void,
+                    width as c_uint,
+                    height as c_uint,
+                    width * size_of::<u32>(),
+                )
+            };
+        }
+        if let Some(audio) = audio {
+            let samples = core.mix_audio();
+            unsafe { audio(samples.as_ptr(), samples.len() / 2) };
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    // Frontends allocate slots once, so this may only depend on the memory
+    // size, which is fixed while a game runs
+    with_core(0, |core| {
+        MachineState::encoded_len(core.machine.ram().len(), STATE_STACK_DEPTH)
+    })
+}
+
+/// # Safety
+///
+/// `data` must point to `size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let out = slice::from_raw_parts_mut(data as *mut u8, size);
+    with_core(false, |core| {
+        let state = core.machine.save_state().to_bytes();
+        if state.len() > size {
+            return false;
+        }
+        // `from_bytes` ignores the padding when loading
+        out[..state.len()].copy_from_slice(&state);
+        out[state.len()..].fill(0);
+
+        true
+    })
+}
+
+/// # Safety
+///
+/// `data` must point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let state = slice::from_raw_parts(data as *const u8, size);
+    with_core(false, |core| match MachineState::from_bytes(state) {
+        Some(state) if core.machine.load_state(&state).is_ok() => {
+            core.crashed = false;
+            true
+        }
+        _ => false,
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+/// # Safety
+///
+/// `game` must be null or point to a `retro_game_info` whose `data` holds
+/// `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const RetroGameInfo) -> bool {
+    let Some(environment) = CALLBACKS.with_borrow(|callbacks| callbacks.environment) else {
+        return false;
+    };
+    let Some(game) = game.as_ref().filter(|game| !game.data.is_null()) else {
+        return false;
+    };
+
+    let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+    if !environment(
+        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT,
+        &mut format as *mut _ as *mut c_void,
+    ) {
+        return false;
+    }
+
+    let Ok(rom) = Rom::from_bytes(slice::from_raw_parts(game.data as *const u8, game.size)) else {
+        return false;
+    };
+    let mut core = Core::new(rom);
+    core.read_options(environment);
+    if !core.start() {
+        return false;
+    }
+
+    CORE.with_borrow_mut(|slot| *slot = Some(core));
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const RetroGameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    CORE.with_borrow_mut(|core| *core = None);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    if id != RETRO_MEMORY_SYSTEM_RAM {
+        return ptr::null_mut();
+    }
+
+    with_core(ptr::null_mut(), |core| {
+        core.machine.ram_mut().as_mut_ptr() as *mut c_void
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    if id != RETRO_MEMORY_SYSTEM_RAM {
+        return 0;
+    }
+
+    with_core(0, |core| core.machine.ram().len())
+}
@@ -0,0 +1,173 @@
+use chip8_core::*;
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::Print,
+    terminal,
+};
+use std::{
+    env,
+    io::{self, Write},
+    process::exit,
+    time::{Duration, Instant},
+};
+
+const TICKS_PER_FRAME: usize = 10;
+const FRAME_TIME: Duration = Duration::from_micros(1_000_000 / 60);
+// Most terminals never report key releases, so a press is held this long
+const HOLD_FRAMES: u32 = 6;
+
+fn key2btn(key: KeyCode) -> Option<usize> {
+    match key {
+        KeyCode::Char('1') => Some(0x1),
+        KeyCode::Char('2') => Some(0x2),
+        KeyCode::Char('3') => Some(0x3),
+        KeyCode::Char('4') => Some(0xC),
+        KeyCode::Char('q') => Some(0x4),
+        KeyCode::Char('w') => Some(0x5),
+        KeyCode::Char('e') => Some(0x6),
+        KeyCode::Char('r') => Some(0xD),
+        KeyCode::Char('a') => Some(0x7),
+        KeyCode::Char('s') => Some(0x8),
+        KeyCode::Char('d') => Some(0x9),
+        KeyCode::Char('f') => Some(0xE),
+        KeyCode::Char('z') => Some(0xA),
+        KeyCode::Char('x') => Some(0x0),
+        KeyCode::Char('c') => Some(0xB),
+        KeyCode::Char('v') => Some(0xF),
+        _ => None,
+    }
+}
+
+/// Draws two display rows per terminal line with half blocks, with the
+/// registers and stack to the right.
+fn draw(chip8: &Machine, out: &mut impl Write) -> io::Result<()> {
+    let (width, height) = chip8.display_dimensions();
+    let screen = chip8.get_display();
+    for y in (0..height).step_by(2) {
+        let line: String = (0..width)
+            .map(
+                |x| match (screen[y * width + x], screen[(y + 1) * width + x]) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                },
+            )
+            .collect();
+        queue!(out, cursor::MoveTo(0, (y / 2) as u16), Print(line))?;
+    }
+
+    let mut panel = vec![
+        format!("PC {:#06X}", chip8.program_counter()),
+        format!("I  {:#06X}", chip8.index_register()),
+        format!("DT {:3}  ST {:3}", chip8.delay_timer(), chip8.sound_timer()),
+        String::new(),
+    ];
+    for (i, v) in chip8.registers().iter().enumerate() {
+        panel.push(format!("V{:X} {:#04X}", i, v));
+    }
+    panel.push(String::new());
+    panel.push("Stack".to_string());
+    for frame in chip8.call_frames().iter().rev() {
+        let target = frame
+            .target
+            .map_or_else(|| "?".to_string(), |addr| format!("{:#06X}", addr));
+        panel.push(format!("  {} ret {:#06X}", target, frame.return_addr));
+    }
+    let column = width as u16 + 2;
+    for (row, text) in panel.iter().enumerate() {
+        queue!(
+            out,
+            cursor::MoveTo(column, row as u16),
+            terminal::Clear(terminal::ClearType::UntilNewLine),
+            Print(text)
+        )?;
+    }
+    // Clear what is left of a deeper stack from the previous frame
+    queue!(
+        out,
+        cursor::MoveTo(column, panel.len() as u16),
+        terminal::Clear(terminal::ClearType::FromCursorDown)
+    )?;
+
+    out.flush()
+}
+
+fn run(chip8: &mut Machine, out: &mut impl Write) -> io::Result<Result<(), Chip8Error>> {
+    let mut held = [0u32; 16];
+    let mut resolution = None;
+    loop {
+        let frame_start = Instant::now();
+        while event::poll(Duration::ZERO)? {
+            if let Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind,
+                ..
+            }) = event::read()?
+            {
+                let ctrl_c =
+                    code == KeyCode::Char('c') && modifiers.contains(KeyModifiers::CONTROL);
+                if code == KeyCode::Esc || ctrl_c {
+                    return Ok(Ok(()));
+                }
+                if let Some(btn) = key2btn(code) {
+                    held[btn] = if kind == KeyEventKind::Release {
+                        0
+                    } else {
+                        HOLD_FRAMES
+                    };
+                }
+            }
+        }
+        for (btn, frames) in held.iter_mut().enumerate() {
+            chip8.keypress(btn, *frames > 0);
+            *frames = frames.saturating_sub(1);
+        }
+
+        // A halted program stays on screen until Escape
+        if let Err(err) = chip8.run_frame() {
+            return Ok(Err(err));
+        }
+        // Every cell is redrawn each frame, so only a resolution change, which
+        // moves the panel, leaves anything stale behind
+        if resolution != Some(chip8.display_dimensions()) {
+            queue!(out, terminal::Clear(terminal::ClearType::All))?;
+            resolution = Some(chip8.display_dimensions());
+        }
+        draw(chip8, out)?;
+
+        if let Some(rest) = FRAME_TIME.checked_sub(frame_start.elapsed()) {
+            std::thread::sleep(rest);
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    let args: Vec<_> = env::args().collect();
+
+    if args.len() != 2 {
+        println!("Usage: cargo run path/to/game");
+        exit(1);
+    }
+
+    let mut chip8 = Machine::new();
+    chip8.set_speed(TICKS_PER_FRAME);
+    chip8.load_from_path(&args[1]).expect("Unable to load ROM");
+
+    let mut out = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+    let result = run(&mut chip8, &mut out);
+    execute!(out, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+
+    if let Err(err) = result? {
+        println!("Error: {}", err);
+        exit(1);
+    }
+
+    Ok(())
+}
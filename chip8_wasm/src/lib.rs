@@ -0,0 +1,124 @@
+use chip8_core::{Chip8Error, Machine};
+use wasm_bindgen::prelude::*;
+
+const FG: [u8; 4] = [255, 255, 255, 255];
+const BG: [u8; 4] = [0, 0, 0, 255];
+
+fn to_js(err: Chip8Error) -> JsError {
+    JsError::new(&err.to_string())
+}
+
+/// Maps a `KeyboardEvent.code` onto the hex keypad, using the same layout as
+/// the desktop frontend.
+#[wasm_bindgen]
+pub fn key2btn(code: &str) -> Option<usize> {
+    match code {
+        "Digit1" => Some(0x1),
+        "Digit2" => Some(0x2),
+        "Digit3" => Some(0x3),
+        "Digit4" => Some(0xC),
+        "KeyQ" => Some(0x4),
+        "KeyW" => Some(0x5),
+        "KeyE" => Some(0x6),
+        "KeyR" => Some(0xD),
+        "KeyA" => Some(0x7),
+        "KeyS" => Some(0x8),
+        "KeyD" => Some(0x9),
+        "KeyF" => Some(0xE),
+        "KeyZ" => Some(0xA),
+        "KeyX" => Some(0x0),
+        "KeyC" => Some(0xB),
+        "KeyV" => Some(0xF),
+        _ => None,
+    }
+}
+
+#[wasm_bindgen]
+pub struct Chip8 {
+    machine: Machine,
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Chip8 {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self {
+            machine: Machine::new(),
+        }
+    }
+
+    /// Resets the machine and loads `rom`, which JS passes as a `Uint8Array`.
+    pub fn load(&mut self, rom: &[u8]) -> Result<(), JsError> {
+        self.machine.reset();
+        self.machine.load(rom).map_err(to_js)
+    }
+
+    pub fn reset(&mut self) {
+        self.machine.reset();
+    }
+
+    pub fn tick(&mut self) -> Result<(), JsError> {
+        self.machine.tick().map_err(to_js)
+    }
+
+    pub fn tick_timers(&mut self) {
+        self.machine.tick_timers();
+    }
+
+    /// Runs one 60 Hz frame; call it from `requestAnimationFrame`. Returns
+    /// false once the program has exited.
+    pub fn run_frame(&mut self) -> Result<bool, JsError> {
+        let outcome = self.machine.run_frame().map_err(to_js)?;
+        Ok(!outcome.halted)
+    }
+
+    pub fn set_speed(&mut self, instructions_per_frame: usize) {
+        self.machine.set_speed(instructions_per_frame);
+    }
+
+    pub fn keypress(&mut self, index: usize, pressed: bool) {
+        self.machine.keypress(index, pressed);
+    }
+
+    pub fn width(&self) -> usize {
+        self.machine.display_dimensions().0
+    }
+
+    pub fn height(&self) -> usize {
+        self.machine.display_dimensions().1
+    }
+
+    /// One byte per pixel, 1 when lit, as a `Uint8Array`.
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.machine
+            .get_display()
+            .iter()
+            .map(|pixel| *pixel as u8)
+            .collect()
+    }
+
+    /// RGBA8 pixels, ready for `new ImageData(new Uint8ClampedArray(..), width)`.
+    pub fn framebuffer_rgba(&self) -> Vec<u8> {
+        let mut rgba = vec![0; self.width() * self.height() * 4];
+        self.machine.render_rgba(&mut rgba, FG, BG);
+        rgba
+    }
+
+    pub fn is_display_dirty(&self) -> bool {
+        self.machine.is_display_dirty()
+    }
+
+    pub fn clear_display_dirty(&mut self) {
+        self.machine.clear_display_dirty();
+    }
+
+    pub fn is_beeping(&self) -> bool {
+        self.machine.is_beeping()
+    }
+}
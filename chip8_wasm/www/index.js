@@ -0,0 +1,54 @@
+// Build with `wasm-pack build --target web` from chip8_wasm/, then serve the
+// crate directory and open www/index.html.
+import init, { Chip8, key2btn } from "../pkg/chip8_wasm.js";
+
+await init();
+
+const chip8 = new Chip8();
+const canvas = document.getElementById("screen");
+const ctx = canvas.getContext("2d");
+let frame = null;
+
+function draw() {
+    canvas.width = chip8.width();
+    canvas.height = chip8.height();
+    const pixels = new Uint8ClampedArray(chip8.framebuffer_rgba());
+    ctx.putImageData(new ImageData(pixels, canvas.width), 0, 0);
+}
+
+function loop() {
+    try {
+        if (!chip8.run_frame()) {
+            return;
+        }
+    } catch (err) {
+        console.error(err);
+        return;
+    }
+    if (chip8.is_display_dirty()) {
+        draw();
+        chip8.clear_display_dirty();
+    }
+    frame = requestAnimationFrame(loop);
+}
+
+document.getElementById("rom").addEventListener("change", async (event) => {
+    const file = event.target.files[0];
+    if (!file) {
+        return;
+    }
+    if (frame !== null) {
+        cancelAnimationFrame(frame);
+    }
+    chip8.load(new Uint8Array(await file.arrayBuffer()));
+    frame = requestAnimationFrame(loop);
+});
+
+for (const [type, pressed] of [["keydown", true], ["keyup", false]]) {
+    document.addEventListener(type, (event) => {
+        const btn = key2btn(event.code);
+        if (btn !== undefined) {
+            chip8.keypress(btn, pressed);
+        }
+    });
+}
@@ -0,0 +1,23 @@
+use chip8_core::{storage::Storage, NUM_RPL_FLAGS};
+use std::{fs, path::PathBuf};
+
+/// Keeps a ROM's SUPER-CHIP user flags in a `.flags` file next to it.
+pub struct FlagsFile(PathBuf);
+
+impl FlagsFile {
+    pub fn for_rom(rom_path: &str) -> Self {
+        Self(PathBuf::from(format!("{}.flags", rom_path)))
+    }
+}
+
+impl Storage for FlagsFile {
+    fn load_flags(&mut self) -> Option<[u8; NUM_RPL_FLAGS]> {
+        fs::read(&self.0).ok()?.try_into().ok()
+    }
+
+    fn save_flags(&mut self, flags: &[u8; NUM_RPL_FLAGS]) {
+        if let Err(err) = fs::write(&self.0, flags) {
+            println!("Unable to save flags to {}: {}", self.0.display(), err);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use sdl2::keyboard::Keycode;
+use std::{collections::HashMap, fs};
+
+/// Keyboard to hex keypad bindings.
+pub struct Keymap(HashMap<Keycode, usize>);
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self(HashMap::from([
+            (Keycode::Num1, 0x1),
+            (Keycode::Num2, 0x2),
+            (Keycode::Num3, 0x3),
+            (Keycode::Num4, 0xC),
+            (Keycode::Q, 0x4),
+            (Keycode::W, 0x5),
+            (Keycode::E, 0x6),
+            (Keycode::R, 0xD),
+            (Keycode::A, 0x7),
+            (Keycode::S, 0x8),
+            (Keycode::D, 0x9),
+            (Keycode::F, 0xE),
+            (Keycode::Z, 0xA),
+            (Keycode::X, 0x0),
+            (Keycode::C, 0xB),
+            (Keycode::V, 0xF),
+        ]))
+    }
+}
+
+impl Keymap {
+    /// Reads one `KEY = N` binding per line, where KEY is an SDL key name
+    /// such as `Up` or `Keypad 5` and N a hex keypad digit. Blank lines and
+    /// lines starting with `#` are ignored.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        let mut keymap = HashMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let binding = line.split_once('=').and_then(|(name, digit)| {
+                let key = Keycode::from_name(name.trim())?;
+                let btn = usize::from_str_radix(digit.trim(), 16)
+                    .ok()
+                    .filter(|btn| *btn < 16)?;
+                Some((key, btn))
+            });
+            match binding {
+                Some((key, btn)) => keymap.insert(key, btn),
+                None => return Err(format!("{}:{}: expected `KEY = 0-F`", path, i + 1)),
+            };
+        }
+
+        Ok(Self(keymap))
+    }
+
+    pub fn get(&self, key: Keycode) -> Option<usize> {
+        self.0.get(&key).copied()
+    }
+}
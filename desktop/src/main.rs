@@ -1,66 +1,175 @@
+mod flags;
+mod keymap;
+
 use chip8_core::*;
+use flags::FlagsFile;
+use keymap::Keymap;
 use sdl2::{
-    event::Event, keyboard::Keycode, pixels::Color, rect::Rect, render::Canvas, video::Window,
+    audio::{AudioCallback, AudioSpecDesired},
+    event::Event,
+    keyboard::Keycode,
+    pixels::Color,
+    rect::Rect,
+    render::Canvas,
+    video::Window,
+};
+use std::{
+    env, fs,
+    process::exit,
+    thread,
+    time::{Duration, Instant},
 };
-use std::{env, fs::File, io::Read, process::exit};
 
-const SCALE: u32 = 20;
-const WINDOW_WIDTH: u32 = (SCREEN_WIDTH as u32) * SCALE;
-const WINDOW_HEIGHT: u32 = (SCREEN_HEIGHT as u32) * SCALE;
+const DEFAULT_SCALE: u32 = 20;
 const TICKS_PER_FRAME: usize = 10;
+const SAMPLE_RATE: i32 = 44100;
+const BEEP_HZ: f32 = 440.0;
+const VOLUME: f32 = 0.25;
+const FRAME_TIME: Duration = Duration::from_micros(1_000_000 / 60);
+
+const USAGE: &str = "Usage: cargo run [-- options] [path/to/game]
+
+Drop a ROM onto the window to load it. P pauses, F5 resets, Escape quits.
+
+Options:
+    --scale N        window pixels per CHIP-8 pixel (default 20)
+    --speed N        instructions per frame (default 10)
+    --profile NAME   vip, schip or xochip
+    --keymap PATH    file of `KEY = N` bindings";
+
+struct Options {
+    rom: Option<String>,
+    scale: u32,
+    speed: usize,
+    quirks: Quirks,
+    keymap: Keymap,
+}
+
+fn fail(message: &str) -> ! {
+    println!("{}\n\n{}", message, USAGE);
+    exit(1);
+}
+
+fn parse_args() -> Options {
+    let mut options = Options {
+        rom: None,
+        scale: DEFAULT_SCALE,
+        speed: TICKS_PER_FRAME,
+        quirks: Quirks::default(),
+        keymap: Keymap::default(),
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scale" => {
+                options.scale = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .filter(|n| *n > 0)
+                    .unwrap_or_else(|| fail("--scale expects a positive number"))
+            }
+            "--speed" => {
+                options.speed = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or_else(|| fail("--speed expects a number"))
+            }
+            "--profile" => {
+                options.quirks = match args.next().as_deref() {
+                    Some("vip") => CompatibilityProfile::CosmacVip.into(),
+                    Some("schip") => CompatibilityProfile::SuperChip.into(),
+                    Some("xochip") => CompatibilityProfile::XoChip.into(),
+                    _ => fail("--profile expects vip, schip or xochip"),
+                }
+            }
+            "--keymap" => {
+                let path = args
+                    .next()
+                    .unwrap_or_else(|| fail("--keymap expects a path"));
+                options.keymap = Keymap::from_file(&path).unwrap_or_else(|err| fail(&err));
+            }
+            _ if arg.starts_with("--") => fail(&format!("Unknown option {}", arg)),
+            _ if options.rom.is_none() => options.rom = Some(arg),
+            _ => fail("Only one ROM can be given"),
+        }
+    }
+
+    options
+}
+
+struct SquareWave {
+    phase: f32,
+    phase_inc: f32,
+    volume: f32,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = if self.phase < 0.5 {
+                self.volume
+            } else {
+                -self.volume
+            };
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+    }
+}
 
-fn draw_screen(emu: &Machine, canvas: &mut Canvas<Window>) {
+fn draw_screen(emu: &Machine, canvas: &mut Canvas<Window>, scale: u32) {
     canvas.set_draw_color(Color::RGB(0, 0, 0));
     canvas.clear();
 
     let screen_buf = emu.get_display();
+    let (width, _) = emu.display_dimensions();
+    let scale = scale * SCREEN_WIDTH as u32 / width as u32;
     canvas.set_draw_color(Color::RGB(255, 255, 255));
     for (i, pixel) in screen_buf.iter().enumerate() {
         if *pixel {
-            let x = (i % SCREEN_WIDTH) as u32;
-            let y = (i / SCREEN_WIDTH) as u32;
+            let x = (i % width) as u32;
+            let y = (i / width) as u32;
 
-            let rect = Rect::new((x * SCALE) as i32, (y * SCALE) as i32, SCALE, SCALE);
+            let rect = Rect::new((x * scale) as i32, (y * scale) as i32, scale, scale);
             canvas.fill_rect(rect).unwrap();
         }
     }
     canvas.present();
 }
 
-fn key2btn(key: Keycode) -> Option<usize> {
-    match key {
-        Keycode::Num1 => Some(0x1),
-        Keycode::Num2 => Some(0x2),
-        Keycode::Num3 => Some(0x3),
-        Keycode::Num4 => Some(0xC),
-        Keycode::Q => Some(0x4),
-        Keycode::W => Some(0x5),
-        Keycode::E => Some(0x6),
-        Keycode::R => Some(0xD),
-        Keycode::A => Some(0x7),
-        Keycode::S => Some(0x8),
-        Keycode::D => Some(0x9),
-        Keycode::F => Some(0xE),
-        Keycode::Z => Some(0xA),
-        Keycode::X => Some(0x0),
-        Keycode::C => Some(0xB),
-        Keycode::V => Some(0xF),
-        _ => None,
+/// Resets `chip8` and loads the ROM at `path`, keeping its bytes for later
+/// resets. Its user flags are kept in a file beside it.
+fn load_rom(chip8: &mut Machine, path: &str) -> Option<Vec<u8>> {
+    let rom = match fs::read(path) {
+        Ok(rom) => rom,
+        Err(err) => {
+            println!("Unable to load ROM {}: {}", path, err);
+            return None;
+        }
+    };
+    chip8.reset();
+    if let Err(err) = chip8.load(&rom) {
+        println!("Unable to load ROM {}: {}", path, err);
+        return None;
     }
+    chip8.set_storage(Box::new(FlagsFile::for_rom(path)));
+
+    Some(rom)
 }
 
 fn main() {
-    let args: Vec<_> = env::args().collect();
-
-    if args.len() != 2 {
-        println!("Usage: cargo run path/to/game");
-        exit(1);
-    }
+    let options = parse_args();
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let window = video_subsystem
-        .window("CHIP-8 Emulator", WINDOW_WIDTH, WINDOW_HEIGHT)
+        .window(
+            "CHIP-8 Emulator",
+            SCREEN_WIDTH as u32 * options.scale,
+            SCREEN_HEIGHT as u32 * options.scale,
+        )
         .position_centered()
         .vulkan()
         .build()
@@ -70,15 +179,29 @@ fn main() {
     canvas.clear();
     canvas.present();
 
+    let audio_subsystem = sdl_context.audio().unwrap();
+    let desired_spec = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE),
+        channels: Some(1),
+        samples: None,
+    };
+    let beeper = audio_subsystem
+        .open_playback(None, &desired_spec, |spec| SquareWave {
+            phase: 0.0,
+            phase_inc: BEEP_HZ / spec.freq as f32,
+            volume: VOLUME,
+        })
+        .unwrap();
+
     let mut event_pump = sdl_context.event_pump().unwrap();
 
-    let mut chip8 = Machine::new();
-    let mut rom = File::open(&args[1]).expect("Unable to open file");
-    let mut buffer = Vec::new();
-    rom.read_to_end(&mut buffer).unwrap();
-    chip8.load(&buffer);
+    let mut chip8 = Machine::new_with_quirks(options.quirks);
+    chip8.set_speed(options.speed);
+    let mut rom = options.rom.and_then(|path| load_rom(&mut chip8, &path));
+    let mut paused = false;
 
     'gameloop: loop {
+        let frame_start = Instant::now();
         for evt in event_pump.poll_iter() {
             match evt {
                 Event::Quit { .. }
@@ -88,27 +211,76 @@ fn main() {
                 } => {
                     break 'gameloop;
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    repeat: false,
+                    ..
+                } => {
+                    paused = !paused;
+                    let title = if paused {
+                        "CHIP-8 Emulator (paused)"
+                    } else {
+                        "CHIP-8 Emulator"
+                    };
+                    canvas.window_mut().set_title(title).unwrap();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => {
+                    if let Some(data) = &rom {
+                        chip8.reset();
+                        chip8.load(data).unwrap();
+                    }
+                }
+                Event::DropFile { filename, .. } => {
+                    if let Some(data) = load_rom(&mut chip8, &filename) {
+                        rom = Some(data);
+                    }
+                }
                 Event::KeyDown {
                     keycode: Some(key), ..
                 } => {
-                    if let Some(k) = key2btn(key) {
+                    if let Some(k) = options.keymap.get(key) {
                         chip8.keypress(k, true);
                     }
                 }
                 Event::KeyUp {
                     keycode: Some(key), ..
                 } => {
-                    if let Some(k) = key2btn(key) {
+                    if let Some(k) = options.keymap.get(key) {
                         chip8.keypress(k, false);
                     }
                 }
                 _ => (),
             }
         }
-        for _ in 0..TICKS_PER_FRAME {
-            chip8.tick();
+
+        if rom.is_some() && !paused {
+            match chip8.run_frame() {
+                Ok(outcome) if outcome.halted => break 'gameloop,
+                Ok(_) => {}
+                Err(err) => {
+                    println!("Error: {}", err);
+                    break 'gameloop;
+                }
+            }
+        }
+
+        if chip8.is_beeping() && !paused {
+            beeper.resume();
+        } else {
+            beeper.pause();
+        }
+
+        if chip8.is_display_dirty() {
+            draw_screen(&chip8, &mut canvas, options.scale);
+            chip8.clear_display_dirty();
+        }
+
+        // Vsync only paces frames that present, so keep 60 Hz by hand
+        if let Some(rest) = FRAME_TIME.checked_sub(frame_start.elapsed()) {
+            thread::sleep(rest);
         }
-        chip8.tick_timers();
-        draw_screen(&chip8, &mut canvas);
     }
 }